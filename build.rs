@@ -1,12 +1,102 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure().compile(
-        &[
-            "proto/identity.proto",
-            "proto/operator_lifecycle.proto",
-            "proto/operator.proto",
-        ],
-        &["proto"],
-    )?;
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("cnpg_descriptor.bin"))
+        .compile(
+            &[
+                "proto/identity.proto",
+                "proto/operator_lifecycle.proto",
+                "proto/operator.proto",
+                "proto/reconciler.proto",
+            ],
+            &["proto"],
+        )?;
     //tonic_build::compile_protos("cnpg-i/proto/identity.proto", "cnpg-i/proto/operator_lifecycle.proto")?;
+
+    // The version follows the SemVer syntax, using the build metadata
+    // to carry the commit and the build day
+    let git_commit = git_commit();
+    let build_date = build_date();
+    println!(
+        "cargo:rustc-env=BUILD_VERSION={}+{}.{}",
+        env::var("CARGO_PKG_VERSION")?,
+        git_commit,
+        build_date[..10].replace('-', "")
+    );
+    println!("cargo:rustc-env=GIT_COMMIT={}", git_commit);
+    println!("cargo:rustc-env=BUILD_DATE={}", build_date);
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
     Ok(())
 }
+
+/// git_commit gets the abbreviated hash of the commit being built, which can
+/// be overridden via the GIT_COMMIT environment variable
+fn git_commit() -> String {
+    if let Ok(commit) = env::var("GIT_COMMIT") {
+        return commit;
+    }
+
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// build_date gets the build date in RFC 3339 format, honoring
+/// SOURCE_DATE_EPOCH for reproducible builds
+fn build_date() -> String {
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        });
+
+    // Convert the number of days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719468;
+    let seconds = timestamp % 86400;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
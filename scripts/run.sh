@@ -12,6 +12,7 @@ fi
 current_context=$(kubectl config view --raw -o json | jq -r '."current-context"' | sed "s/kind-//")
 kind load docker-image --name=${current_context} plugin-generic-exporter:${VERSION:-latest}
 
+kubectl apply -f kubernetes/rbac.yaml -f kubernetes/plugin-config.yaml
 kubectl patch deployment -n cnpg-system  cnpg-controller-manager --patch-file  kubernetes/deployment-patch.json
 kubectl rollout restart deployment -n cnpg-system  cnpg-controller-manager
 kubectl rollout status deployment -n cnpg-system  cnpg-controller-manager
\ No newline at end of file
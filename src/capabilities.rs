@@ -0,0 +1,175 @@
+use crate::cnpg;
+use log::info;
+
+/// Service is a CNPG-i service that can be implemented by this plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    #[cfg(feature = "lifecycle")]
+    Lifecycle,
+    #[cfg(feature = "operator")]
+    Operator,
+    #[cfg(feature = "reconciler")]
+    Reconciler,
+}
+
+impl Service {
+    /// name is the name used to refer to this service in the
+    /// DISABLED_SERVICES_ENV environment variable
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "lifecycle")]
+            Service::Lifecycle => "lifecycle",
+            #[cfg(feature = "operator")]
+            Service::Operator => "operator",
+            #[cfg(feature = "reconciler")]
+            Service::Reconciler => "reconciler",
+        }
+    }
+
+    fn plugin_capability(&self) -> cnpg::PluginCapability {
+        let service_type: cnpg::plugin_capability::service::Type = match *self {
+            #[cfg(feature = "lifecycle")]
+            Service::Lifecycle => cnpg::plugin_capability::service::Type::LifecycleService,
+            #[cfg(feature = "operator")]
+            Service::Operator => cnpg::plugin_capability::service::Type::OperatorService,
+            #[cfg(feature = "reconciler")]
+            Service::Reconciler => cnpg::plugin_capability::service::Type::ReconcilerHooks,
+        };
+
+        cnpg::PluginCapability {
+            r#type: Some(cnpg::plugin_capability::Type::Service(
+                cnpg::plugin_capability::Service {
+                    r#type: service_type.into(),
+                },
+            )),
+        }
+    }
+}
+
+/// Registry collects the services implemented by the plugin and the
+/// RPCs they support. The capabilities advertised to CNPG are derived
+/// from this registry, as the services added to the gRPC server are.
+#[derive(Debug, Default)]
+pub struct Registry {
+    disabled: Vec<String>,
+    services: Vec<Service>,
+    #[cfg(feature = "operator")]
+    operator_rpcs: Vec<cnpg::operator_capability::rpc::Type>,
+}
+
+impl Registry {
+    /// new creates an empty registry, where the services in the passed list
+    /// are disabled at runtime
+    pub fn new(disabled: Vec<String>) -> Self {
+        Registry {
+            disabled,
+            ..Default::default()
+        }
+    }
+
+    /// from_env creates an empty registry, where the services listed in
+    /// the DISABLED_SERVICES_ENV environment variable are disabled
+    pub fn from_env() -> Self {
+        let disabled = std::env::var(crate::consts::DISABLED_SERVICES_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        Registry::new(disabled)
+    }
+
+    /// register adds a service to the registry, returning false if the
+    /// service has been disabled at runtime. The services not compiled in
+    /// can't be registered
+    pub fn register(&mut self, service: Service) -> bool {
+        if self.disabled.iter().any(|x| x == service.name()) {
+            info!("Service {} disabled", service.name());
+            return false;
+        }
+
+        if !self.services.contains(&service) {
+            self.services.push(service);
+        }
+        true
+    }
+
+    /// register_operator_rpc declares an RPC supported by the operator service
+    #[cfg(feature = "operator")]
+    pub fn register_operator_rpc(&mut self, rpc: cnpg::operator_capability::rpc::Type) {
+        if !self.operator_rpcs.contains(&rpc) {
+            self.operator_rpcs.push(rpc);
+        }
+    }
+
+    /// is_enabled tells if a service has been registered
+    pub fn is_enabled(&self, service: Service) -> bool {
+        self.services.contains(&service)
+    }
+
+    /// plugin_capabilities are the capabilities to be advertised
+    /// by the identity service
+    pub fn plugin_capabilities(&self) -> Vec<cnpg::PluginCapability> {
+        self.services
+            .iter()
+            .map(|service| service.plugin_capability())
+            .collect()
+    }
+
+    /// operator_capabilities are the capabilities to be advertised
+    /// by the operator service
+    #[cfg(feature = "operator")]
+    pub fn operator_capabilities(&self) -> Vec<cnpg::OperatorCapability> {
+        self.operator_rpcs
+            .iter()
+            .map(|rpc| cnpg::OperatorCapability {
+                r#type: Some(cnpg::operator_capability::Type::Rpc(
+                    cnpg::operator_capability::Rpc {
+                        r#type: (*rpc).into(),
+                    },
+                )),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "lifecycle", feature = "operator"))]
+    fn test_registered_services_are_advertised() {
+        let mut registry = Registry::new(vec![]);
+        assert!(registry.register(Service::Lifecycle));
+        assert!(registry.register(Service::Operator));
+
+        assert!(registry.is_enabled(Service::Lifecycle));
+        assert_eq!(registry.plugin_capabilities().len(), 2);
+    }
+
+    #[test]
+    #[cfg(all(feature = "lifecycle", feature = "operator"))]
+    fn test_disabled_services_are_not_advertised() {
+        let mut registry = Registry::new(vec!["operator".to_string()]);
+        assert!(registry.register(Service::Lifecycle));
+        assert!(!registry.register(Service::Operator));
+
+        assert!(!registry.is_enabled(Service::Operator));
+        assert_eq!(
+            registry.plugin_capabilities(),
+            vec![Service::Lifecycle.plugin_capability()]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "operator")]
+    fn test_operator_rpcs() {
+        let mut registry = Registry::new(vec![]);
+        registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::MutateCluster);
+        registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::MutateCluster);
+
+        assert_eq!(registry.operator_capabilities().len(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{consts, exporter};
+use log::info;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// CATALOG is the image catalog used to resolve the exporter images
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// CatalogEntry is an exporter image, identified by its exporter
+/// type and version
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogEntry {
+    /// exporter_type is the exporter implementation packaged in the image
+    pub exporter_type: String,
+
+    /// version is the value of the exporterVersion parameter selecting
+    /// this image
+    pub version: String,
+
+    /// image is the image reference, which should be pinned by digest
+    pub image: String,
+
+    /// default marks the image used when no version is requested
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// Catalog is the list of the known exporter images
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    entries: Vec<CatalogEntry>,
+}
+
+/// load initializes the image catalog from the environment. When the
+/// catalog file can't be loaded only the built-in entries are available,
+/// and the error is returned to mark the plugin as not ready
+pub fn load() -> Result<(), String> {
+    let (catalog, result) = match Catalog::from_env() {
+        Ok(catalog) => (catalog, Ok(())),
+        Err(err) => (Catalog::builtin(), Err(err)),
+    };
+    let _ = CATALOG.set(catalog);
+    result
+}
+
+/// get is the image catalog, which only contains the built-in entries
+/// until it is loaded
+pub fn get() -> &'static Catalog {
+    CATALOG.get_or_init(Catalog::builtin)
+}
+
+impl Catalog {
+    /// builtin is the catalog containing the default image of every
+    /// exporter type, keyed by the version it is pinned to
+    pub fn builtin() -> Catalog {
+        Catalog {
+            entries: exporter::BACKENDS
+                .iter()
+                .map(|backend| CatalogEntry {
+                    exporter_type: backend.name().to_string(),
+                    version: backend.default_version().to_string(),
+                    image: backend.default_image().to_string(),
+                    default: true,
+                })
+                .collect(),
+        }
+    }
+
+    /// from_env is the built-in catalog extended with the entries of
+    /// the file referenced by the IMAGE_CATALOG_FILE environment variable
+    pub fn from_env() -> Result<Catalog, String> {
+        match std::env::var(consts::IMAGE_CATALOG_FILE_ENV) {
+            Ok(path) => Catalog::from_file(&path),
+            Err(_) => Ok(Catalog::builtin()),
+        }
+    }
+
+    /// from_file is the built-in catalog extended with the entries of
+    /// the passed file
+    pub fn from_file(path: &str) -> Result<Catalog, String> {
+        let entries = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|content| {
+                serde_yaml::from_str::<Vec<CatalogEntry>>(&content).map_err(|err| err.to_string())
+            })
+            .map_err(|err| {
+                format!(
+                    "error while loading the image catalog from {}: {}",
+                    path, err
+                )
+            })?;
+        info!(
+            "Loaded {} image catalog entries from {}",
+            entries.len(),
+            path
+        );
+        Ok(Catalog::builtin().with_entries(entries))
+    }
+
+    /// with_entries adds the passed entries to the catalog, replacing the
+    /// ones having the same exporter type and version
+    pub fn with_entries(mut self, entries: Vec<CatalogEntry>) -> Catalog {
+        for entry in entries {
+            self.entries
+                .retain(|x| x.exporter_type != entry.exporter_type || x.version != entry.version);
+            if entry.default {
+                self.entries
+                    .iter_mut()
+                    .filter(|x| x.exporter_type == entry.exporter_type)
+                    .for_each(|x| x.default = false);
+            }
+            self.entries.push(entry);
+        }
+        self
+    }
+
+    /// default_image is the image used for an exporter type when no
+    /// version is requested
+    pub fn default_image(&self, exporter_type: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|x| x.exporter_type == exporter_type && x.default)
+            .map(|x| x.image.as_str())
+    }
+
+    /// image is the image of an exporter type with the passed version
+    pub fn image(&self, exporter_type: &str, version: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|x| x.exporter_type == exporter_type && x.version == version)
+            .map(|x| x.image.as_str())
+    }
+
+    /// versions is the comma-separated list of the known versions
+    /// of an exporter type
+    pub fn versions(&self, exporter_type: &str) -> String {
+        self.entries
+            .iter()
+            .filter(|x| x.exporter_type == exporter_type)
+            .map(|x| x.version.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = Catalog::builtin();
+
+        assert_eq!(
+            catalog.default_image("justwatchcom-sql-exporter"),
+            Some(consts::IMAGE_NAME_PARAMETER_DEFAULT)
+        );
+        assert_eq!(
+            catalog.image("justwatchcom-sql-exporter", "0.5"),
+            Some(consts::IMAGE_NAME_PARAMETER_DEFAULT)
+        );
+        assert_eq!(
+            catalog.image("postgres-exporter", "0.15.0"),
+            Some("quay.io/prometheuscommunity/postgres-exporter:v0.15.0")
+        );
+        assert!(catalog
+            .image("justwatchcom-sql-exporter", "latest")
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_catalog_file() {
+        let path = std::env::temp_dir().join("test_invalid_catalog_file.yaml");
+        std::fs::write(&path, "- exporterType: justwatchcom-sql-exporter\n").unwrap();
+
+        let result = Catalog::from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().contains("missing field `version`"));
+
+        assert!(Catalog::from_file("/nonexistent/catalog.yaml").is_err());
+    }
+
+    #[test]
+    fn test_catalog_entries() {
+        let entries: Vec<CatalogEntry> = serde_yaml::from_str(
+            r#"
+- exporterType: justwatchcom-sql-exporter
+  version: "0.5"
+  image: registry.example.com/sql_exporter@sha256:0123
+  default: true
+- exporterType: justwatchcom-sql-exporter
+  version: "0.4"
+  image: registry.example.com/sql_exporter@sha256:4567
+"#,
+        )
+        .unwrap();
+        let catalog = Catalog::builtin().with_entries(entries);
+
+        assert_eq!(
+            catalog.default_image("justwatchcom-sql-exporter"),
+            Some("registry.example.com/sql_exporter@sha256:0123")
+        );
+        assert_eq!(
+            catalog.image("justwatchcom-sql-exporter", "0.4"),
+            Some("registry.example.com/sql_exporter@sha256:4567")
+        );
+        assert_eq!(catalog.versions("justwatchcom-sql-exporter"), "0.5, 0.4");
+    }
+}
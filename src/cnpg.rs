@@ -1,3 +1,8 @@
 tonic::include_proto!("cnpgi.identity.v1");
 tonic::include_proto!("cnpgi.operator.v1");
 tonic::include_proto!("cnpgi.operator_lifecycle.v1");
+tonic::include_proto!("cnpgi.reconciler.v1");
+
+/// FILE_DESCRIPTOR_SET is the encoded descriptor of the CNPG-i services
+/// implemented by this plugin, used by the gRPC server reflection
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("cnpg_descriptor");
@@ -0,0 +1,2062 @@
+use crate::{
+    catalog, consts,
+    exporter::{self, ExporterBackend},
+    helper::{self, DataLoader},
+};
+use k8s_openapi::{api::core::v1 as api, apimachinery::pkg::apis::meta::v1::LabelSelector};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// DEFAULT_CONTAINER_NAME is the name of the exporter container when
+/// the exporters parameter is not used
+const DEFAULT_CONTAINER_NAME: &str = "sql-exporter";
+
+/// DEFAULT_PORT_NAME is the name of the exporter port when the exporters
+/// parameter is not used
+const DEFAULT_PORT_NAME: &str = "exporter";
+
+/// RESERVED_PORTS are the ports already used by CNPG inside the instance
+/// Pods: PostgreSQL, the instance manager status and the metrics
+const RESERVED_PORTS: &[u16] = &[5432, 8000, 9187];
+
+/// RESERVED_CONTAINER_NAMES are the names of the containers created by CNPG
+const RESERVED_CONTAINER_NAMES: &[&str] = &["postgres", "bootstrap-controller"];
+
+/// POSTGRES_DATABASE is the database reserved by CNPG, which can't be
+/// managed via Database resources
+const POSTGRES_DATABASE: &str = "postgres";
+
+/// MAX_RESOURCE_NAME_LENGTH is the maximum length of the name of a
+/// Kubernetes resource
+const MAX_RESOURCE_NAME_LENGTH: usize = 253;
+
+/// IMAGE_PULL_POLICIES are the valid values of the imagePullPolicy parameter
+const IMAGE_PULL_POLICIES: &[&str] = &["Always", "IfNotPresent", "Never"];
+
+/// MAX_EXPORTER_NAME_LENGTH is the maximum length of an exporter name, which
+/// is used as the name of its port
+const MAX_EXPORTER_NAME_LENGTH: usize = 15;
+
+/// InstanceRole is the role of a PostgreSQL instance inside the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceRole {
+    Primary,
+    Replica,
+}
+
+impl InstanceRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstanceRole::Primary => "primary",
+            InstanceRole::Replica => "replica",
+        }
+    }
+}
+
+/// ScratchDataMount is how the CNPG scratch-data volume, containing the
+/// runtime directory of the instance manager, is mounted in the exporter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScratchDataMount {
+    /// Socket mounts only the PostgreSQL socket directory, read-only.
+    /// Unix sockets can be connected to even on read-only mounts
+    Socket,
+
+    /// Full mounts the whole volume, read-write, on /controller and /run
+    Full,
+}
+
+impl ScratchDataMount {
+    /// VALUES are the valid values of the scratchDataMount parameter
+    const VALUES: &'static [&'static str] = &["socket", "full"];
+
+    /// as_str is the value of the scratchDataMount parameter selecting
+    /// this mode
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScratchDataMount::Socket => "socket",
+            ScratchDataMount::Full => "full",
+        }
+    }
+
+    fn parse(value: &str) -> Option<ScratchDataMount> {
+        match value {
+            "socket" => Some(ScratchDataMount::Socket),
+            "full" => Some(ScratchDataMount::Full),
+            _ => None,
+        }
+    }
+
+    /// mount_paths are the paths where the scratch-data volume is mounted
+    pub fn mount_paths(&self) -> &'static [&'static str] {
+        match self {
+            ScratchDataMount::Socket => &[exporter::POSTGRES_SOCKET_DIRECTORY],
+            ScratchDataMount::Full => &[exporter::CONTROLLER_DIRECTORY, exporter::RUN_DIRECTORY],
+        }
+    }
+}
+
+/// ImagePullSecret is an entry of the imagePullSecrets parameter, which can
+/// be either the name of a Secret or a Kubernetes LocalObjectReference
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImagePullSecret {
+    Name(String),
+    Reference { name: String },
+}
+
+impl ImagePullSecret {
+    fn name(self) -> String {
+        match self {
+            ImagePullSecret::Name(name) | ImagePullSecret::Reference { name } => name,
+        }
+    }
+}
+
+/// ParameterError is an invalid plugin parameter value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterError {
+    /// parameter is the name of the plugin parameter containing the error
+    pub parameter: String,
+
+    /// message describes the error
+    pub message: String,
+}
+
+/// ExporterConfig is the configuration of an exporter sidecar,
+/// as computed from the plugin parameters
+pub struct ExporterConfig {
+    /// container_name is the name of the sidecar container
+    pub container_name: String,
+
+    /// volume_name is the name of the volume containing the exporter
+    /// configuration
+    pub volume_name: String,
+
+    /// port_name is the name of the port serving the metrics
+    pub port_name: String,
+
+    /// backend is the exporter implementation
+    pub backend: &'static dyn ExporterBackend,
+
+    /// image is the image of the exporter
+    pub image: String,
+
+    /// image_pull_policy is the pull policy of the exporter image, when
+    /// not using the Kubernetes default
+    pub image_pull_policy: Option<String>,
+
+    /// image_pull_secrets are the names of the Secrets used to pull
+    /// the exporter image
+    pub image_pull_secrets: Vec<String>,
+
+    /// primary_config_map_name is the name of the ConfigMap containing the
+    /// exporter configuration used in the primary instance
+    pub primary_config_map_name: String,
+
+    /// replica_config_map_name is the name of the ConfigMap containing the
+    /// exporter configuration used in the replicas
+    pub replica_config_map_name: String,
+
+    /// port is the port where the exporter serves its metrics
+    pub port: u16,
+
+    /// log_level is the verbosity of the exporter
+    pub log_level: String,
+
+    /// log_format is the format of the exporter logs, when not using
+    /// the exporter default
+    pub log_format: Option<String>,
+
+    /// env are the additional environment variables of the exporter,
+    /// replacing the ones set by the plugin with the same name
+    pub env: Vec<api::EnvVar>,
+
+    /// env_from are the sources of environment variables of the exporter
+    pub env_from: Vec<api::EnvFromSource>,
+
+    /// command replaces the entrypoint of the exporter image
+    pub command: Option<Vec<String>>,
+
+    /// extra_args are appended to the arguments computed by the plugin
+    pub extra_args: Vec<String>,
+
+    /// volumes are the additional volumes used by the exporter
+    pub volumes: Vec<api::Volume>,
+
+    /// volume_mounts are the additional mounts of the exporter container
+    pub volume_mounts: Vec<api::VolumeMount>,
+
+    /// scratch_data_mount is how the CNPG scratch-data volume is mounted
+    pub scratch_data_mount: ScratchDataMount,
+
+    /// resources are the compute resources of the exporter container
+    pub resources: Option<api::ResourceRequirements>,
+
+    /// security_context is the security context of the exporter container
+    pub security_context: Option<api::SecurityContext>,
+}
+
+impl ExporterConfig {
+    /// config_map_name is the name of the ConfigMap containing the exporter
+    /// configuration to be used for an instance with the passed role
+    pub fn config_map_name(&self, role: InstanceRole) -> &str {
+        match role {
+            InstanceRole::Primary => &self.primary_config_map_name,
+            InstanceRole::Replica => &self.replica_config_map_name,
+        }
+    }
+
+    /// env is the environment of the exporter container for an instance
+    /// with the passed role
+    pub fn env(&self, role: InstanceRole) -> Vec<api::EnvVar> {
+        let mut result = self.plugin_env(role);
+        for var in &self.env {
+            match result.iter_mut().find(|x| x.name == var.name) {
+                Some(existing) => *existing = var.clone(),
+                None => result.push(var.clone()),
+            }
+        }
+        result
+    }
+
+    /// plugin_env are the environment variables the plugin sets in the
+    /// exporter container
+    fn plugin_env(&self, role: InstanceRole) -> Vec<api::EnvVar> {
+        let mut result = exporter::pod_info_env(role.as_str());
+        result.extend(self.backend.env(&self.settings()));
+        result
+    }
+
+    /// args are the arguments of the exporter container
+    pub fn args(&self) -> Vec<String> {
+        let mut result = self.backend.args(&self.settings());
+        result.extend(self.extra_args.iter().cloned());
+        result
+    }
+
+    /// scrape_annotations are the annotations allowing Prometheus
+    /// annotation-based discovery to scrape this exporter
+    pub fn scrape_annotations(&self) -> BTreeMap<String, String> {
+        let annotation = |name: &str| format!("{}{}", consts::SCRAPE_ANNOTATION_PREFIX, name);
+        BTreeMap::from([
+            (annotation("scrape"), "true".to_string()),
+            (annotation("port"), self.port.to_string()),
+            (annotation("path"), self.backend.metrics_path().to_string()),
+        ])
+    }
+
+    /// settings are the values used to run this exporter
+    pub fn settings(&self) -> exporter::ExporterSettings {
+        exporter::ExporterSettings {
+            config_path: format!(
+                "{}/{}",
+                exporter::CONFIG_DIRECTORY,
+                self.backend.config_file_name()
+            ),
+            port: self.port,
+            log_level: self.log_level.clone(),
+            log_format: self.log_format.clone(),
+        }
+    }
+}
+
+/// MonitoringSettings are the PostgreSQL settings required by the
+/// exporter queries
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MonitoringSettings {
+    /// stat_statements tells if pg_stat_statements should be preloaded
+    pub stat_statements: bool,
+
+    /// track_io_timing tells if the timing of I/O calls should be collected
+    pub track_io_timing: bool,
+
+    /// databases are the databases where the extensions are created
+    pub databases: Vec<MonitoringDatabase>,
+
+    /// extensions are the extensions created in the databases
+    pub extensions: Vec<String>,
+}
+
+/// MonitoringDatabase is a database where the monitoring extensions
+/// are created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitoringDatabase {
+    /// name is the name of the database
+    pub name: String,
+}
+
+impl MonitoringDatabase {
+    /// resource_name is the name of the Database resource managing the
+    /// monitoring extensions of this database
+    pub fn resource_name(&self, cluster: &str) -> String {
+        format!(
+            "{}-monitoring-{}",
+            cluster,
+            self.name.to_lowercase().replace('_', "-")
+        )
+    }
+}
+
+/// MonitoringDatabaseEntry is an entry of the monitoringDatabases parameter,
+/// which can be either the name of a database or an object with its name.
+/// The owner is not supported, as the plugin doesn't manage the databases
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MonitoringDatabaseEntry {
+    Name(String),
+    Definition { name: String, owner: Option<String> },
+}
+
+impl MonitoringDatabaseEntry {
+    /// name is the name of the database
+    fn name(&self) -> &str {
+        match self {
+            MonitoringDatabaseEntry::Name(name) => name,
+            MonitoringDatabaseEntry::Definition { name, .. } => name,
+        }
+    }
+}
+
+/// load_monitoring_settings computes the PostgreSQL settings required
+/// by the exporters from the plugin parameters
+pub fn load_monitoring_settings(
+    loader: &DataLoader,
+) -> Result<MonitoringSettings, Vec<ParameterError>> {
+    let mut errors = Vec::new();
+    let mut error = |parameter: &str, message: String| {
+        errors.push(ParameterError {
+            parameter: parameter.to_string(),
+            message,
+        })
+    };
+    let mut flag = |parameter: &str| {
+        loader
+            .get_bool_parameter(parameter)
+            .unwrap_or_else(|message| {
+                error(parameter, message);
+                None
+            })
+            .unwrap_or_default()
+    };
+
+    let stat_statements = flag(consts::ENABLE_STAT_STATEMENTS_PARAMETER_NAME);
+    let track_io_timing = flag(consts::ENABLE_TRACK_IO_TIMING_PARAMETER_NAME);
+
+    let extensions: Vec<String> = loader
+        .get_structured_parameter(consts::MONITORING_EXTENSIONS_PARAMETER_NAME)
+        .unwrap_or_else(|message| {
+            error(
+                consts::MONITORING_EXTENSIONS_PARAMETER_NAME,
+                format!("expected a list, {}", message),
+            );
+            None
+        })
+        .unwrap_or_else(|| {
+            consts::MONITORING_EXTENSIONS_DEFAULT
+                .iter()
+                .map(|x| x.to_string())
+                .collect()
+        });
+    if extensions.iter().any(|x| x.is_empty()) {
+        error(
+            consts::MONITORING_EXTENSIONS_PARAMETER_NAME,
+            "the extension name can't be empty".to_string(),
+        );
+    }
+
+    let entries: Vec<MonitoringDatabaseEntry> = loader
+        .get_structured_parameter(consts::MONITORING_DATABASES_PARAMETER_NAME)
+        .unwrap_or_else(|message| {
+            error(
+                consts::MONITORING_DATABASES_PARAMETER_NAME,
+                format!("expected a list, {}", message),
+            );
+            None
+        })
+        .unwrap_or_default();
+
+    let cluster_name = loader.cluster_field("/metadata/name").unwrap_or_default();
+    let mut databases: Vec<MonitoringDatabase> = Vec::new();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let (name, owner) = match entry {
+            MonitoringDatabaseEntry::Name(name) => (name, None),
+            MonitoringDatabaseEntry::Definition { name, owner } => (name, owner),
+        };
+
+        if owner.is_some() {
+            error(
+                consts::MONITORING_DATABASES_PARAMETER_NAME,
+                format!(
+                    "[{}] ({}): owner: the plugin doesn't manage the databases, so their owner can't be set",
+                    idx, name
+                ),
+            );
+        } else if name.is_empty() {
+            error(
+                consts::MONITORING_DATABASES_PARAMETER_NAME,
+                format!("[{}]: name: this field is required", idx),
+            );
+        } else if name == POSTGRES_DATABASE {
+            // The postgres database is reserved by CNPG, and is reported
+            // by load_warnings
+            continue;
+        } else if databases.iter().any(|x| x.name == name) {
+            error(
+                consts::MONITORING_DATABASES_PARAMETER_NAME,
+                format!("duplicate database {}", name),
+            );
+        } else {
+            let database = MonitoringDatabase { name };
+            let resource_name = database.resource_name(cluster_name);
+            if let Err(message) = validate_resource_name(&resource_name) {
+                error(
+                    consts::MONITORING_DATABASES_PARAMETER_NAME,
+                    format!(
+                        "[{}] ({}): the name of its Database resource, {}, is not valid: {}",
+                        idx, database.name, resource_name, message
+                    ),
+                );
+            } else if let Some(other) = databases
+                .iter()
+                .find(|x| x.resource_name(cluster_name) == resource_name)
+            {
+                error(
+                    consts::MONITORING_DATABASES_PARAMETER_NAME,
+                    format!(
+                        "[{}] ({}): the name of its Database resource, {}, is already used by database {}",
+                        idx, database.name, resource_name, other.name
+                    ),
+                );
+            } else {
+                databases.push(database);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(MonitoringSettings {
+            stat_statements,
+            track_io_timing,
+            databases,
+            extensions,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// ScrapeSettings are the resources created by the plugin to allow
+/// Prometheus to scrape the exporters
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScrapeSettings {
+    /// service tells if a headless Service selecting the instances
+    /// should be created
+    pub service: bool,
+
+    /// annotations tells if the instance Pods should be annotated
+    /// for Prometheus annotation-based discovery
+    pub annotations: bool,
+
+    /// network_policy selects the Pods allowed to reach the exporters,
+    /// and is missing when no NetworkPolicy should be created
+    pub network_policy: Option<NetworkPolicySettings>,
+}
+
+/// NetworkPolicySettings selects the Prometheus Pods allowed to reach the
+/// exporters. When only the namespaces are selected, every Pod in them
+/// is allowed, and when only the Pods are selected, they need to be in
+/// the namespace of the cluster
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NetworkPolicySettings {
+    /// namespace_selector selects the namespaces of the allowed Pods
+    pub namespace_selector: Option<LabelSelector>,
+
+    /// pod_selector selects the allowed Pods
+    pub pod_selector: Option<LabelSelector>,
+}
+
+/// load_scrape_settings computes the scrape settings from the plugin parameters
+pub fn load_scrape_settings(loader: &DataLoader) -> Result<ScrapeSettings, Vec<ParameterError>> {
+    let mut errors = Vec::new();
+    let mut error = |parameter: &str, message: String| {
+        errors.push(ParameterError {
+            parameter: parameter.to_string(),
+            message,
+        })
+    };
+    let mut flag = |parameter: &str| {
+        loader
+            .get_bool_parameter(parameter)
+            .unwrap_or_else(|message| {
+                error(parameter, message);
+                None
+            })
+            .unwrap_or_default()
+    };
+
+    let service = flag(consts::CREATE_SERVICE_PARAMETER_NAME);
+    let annotations = flag(consts::SCRAPE_ANNOTATIONS_PARAMETER_NAME);
+
+    let network_policy: Option<NetworkPolicySettings> = loader
+        .get_structured_parameter(consts::NETWORK_POLICY_PARAMETER_NAME)
+        .unwrap_or_else(|message| {
+            error(consts::NETWORK_POLICY_PARAMETER_NAME, message);
+            None
+        });
+    if network_policy
+        .as_ref()
+        .is_some_and(|x| x.namespace_selector.is_none() && x.pod_selector.is_none())
+    {
+        error(
+            consts::NETWORK_POLICY_PARAMETER_NAME,
+            "namespaceSelector or podSelector is required".to_string(),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(ScrapeSettings {
+            service,
+            annotations,
+            network_policy,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// default_parameters are the values the plugin uses for the parameters
+/// which are not specified, given the specified ones. The port of the
+/// entries of the exporters list needs to be unique, and it's not included
+pub fn default_parameters(parameters: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut result: HashMap<String, String> = [
+        (
+            consts::EXPORTER_LOG_LEVEL_PARAMETER_NAME,
+            consts::EXPORTER_LOG_LEVEL_DEFAULT,
+        ),
+        (
+            consts::SCRATCH_DATA_MOUNT_PARAMETER_NAME,
+            ScratchDataMount::Socket.as_str(),
+        ),
+        (consts::ALLOW_ENV_OVERRIDE_PARAMETER_NAME, "false"),
+        (consts::ENABLE_STAT_STATEMENTS_PARAMETER_NAME, "false"),
+        (consts::ENABLE_TRACK_IO_TIMING_PARAMETER_NAME, "false"),
+        (consts::CREATE_SERVICE_PARAMETER_NAME, "false"),
+        (consts::SCRAPE_ANNOTATIONS_PARAMETER_NAME, "false"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    result.insert(
+        consts::MONITORING_EXTENSIONS_PARAMETER_NAME.to_string(),
+        serde_json::json!(consts::MONITORING_EXTENSIONS_DEFAULT).to_string(),
+    );
+
+    // The defaults of an unknown exporter type are not known
+    if let Some(backend) = exporter::get_backend(
+        parameters
+            .get(consts::EXPORTER_TYPE_PARAMETER_NAME)
+            .map(|x| x.as_str()),
+    ) {
+        result.insert(
+            consts::EXPORTER_TYPE_PARAMETER_NAME.to_string(),
+            backend.name().to_string(),
+        );
+        if !parameters.contains_key(consts::EXPORTERS_PARAMETER_NAME) {
+            result.insert(
+                consts::PORT_PARAMETER_NAME.to_string(),
+                backend.default_port().to_string(),
+            );
+        }
+    }
+
+    result
+}
+
+/// load_exporters computes the configuration of every exporter sidecar. When
+/// the exporters parameter is used, every entry of that list defines an
+/// exporter, otherwise the plugin parameters define a single one
+pub fn load_exporters(loader: &DataLoader) -> Result<Vec<ExporterConfig>, Vec<ParameterError>> {
+    let parameters = loader.copy_parameters();
+
+    if loader
+        .get_parameter(consts::EXPORTERS_PARAMETER_NAME)
+        .is_none()
+    {
+        return parse_exporter(
+            DEFAULT_CONTAINER_NAME,
+            &format!("{}-configuration", DEFAULT_CONTAINER_NAME),
+            DEFAULT_PORT_NAME,
+            &parameters,
+            None,
+        )
+        .map(|config| vec![config]);
+    }
+
+    let exporters_error = |message: String| ParameterError {
+        parameter: consts::EXPORTERS_PARAMETER_NAME.to_string(),
+        message,
+    };
+
+    let entries: Vec<HashMap<String, serde_json::Value>> = loader
+        .get_structured_parameter(consts::EXPORTERS_PARAMETER_NAME)
+        .map_err(|err| {
+            vec![exporters_error(format!(
+                "expected a list of exporter definitions, {}",
+                err
+            ))]
+        })?
+        .unwrap_or_default();
+    if entries.is_empty() {
+        return Err(vec![exporters_error(
+            "at least one exporter is required".to_string(),
+        )]);
+    }
+
+    let mut result: Vec<ExporterConfig> = Vec::new();
+    let mut errors: Vec<ParameterError> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        let entry_error = |message: &str| exporters_error(format!("[{}]: {}", idx, message));
+
+        // Every entry inherits the plugin parameters, overriding them
+        let mut entry_parameters = parameters.clone();
+        entry_parameters.remove(consts::EXPORTERS_PARAMETER_NAME);
+        let mut valid = true;
+        for (key, value) in entry {
+            match helper::coerce_parameter_value(value) {
+                Ok(value) => {
+                    entry_parameters.insert(key.clone(), value);
+                }
+                Err(message) => {
+                    errors.push(entry_error(&format!("{}: {}", key, message)));
+                    valid = false;
+                }
+            }
+        }
+        if !valid {
+            continue;
+        }
+
+        // The image of the plugin parameters belongs to their exporter type,
+        // so an entry using another one gets the image of its own type
+        let backend_name = |parameters: &HashMap<String, String>| {
+            exporter::get_backend(
+                parameters
+                    .get(consts::EXPORTER_TYPE_PARAMETER_NAME)
+                    .map(|x| x.as_str()),
+            )
+            .map(|x| x.name())
+        };
+        if backend_name(&entry_parameters) != backend_name(&parameters) {
+            for key in [
+                consts::IMAGE_NAME_PARAMETER_NAME,
+                consts::EXPORTER_VERSION_PARAMETER_NAME,
+            ] {
+                if !entry.contains_key(key) {
+                    entry_parameters.remove(key);
+                }
+            }
+        }
+
+        let Some(name) = entry_parameters.remove(consts::EXPORTER_NAME_FIELD) else {
+            errors.push(entry_error("name: this field is required"));
+            continue;
+        };
+        if let Err(message) = validate_exporter_name(&name) {
+            errors.push(entry_error(&format!("name: {}", message)));
+            continue;
+        }
+
+        match parse_exporter(
+            &name,
+            &format!("{}-configuration", name),
+            &name,
+            &entry_parameters,
+            Some(&format!("[{}] ({})", idx, name)),
+        ) {
+            Ok(config) => result.push(config),
+            Err(err) => errors.extend(err),
+        }
+    }
+
+    // Names and ports need to be unique across the exporters
+    for (idx, config) in result.iter().enumerate() {
+        let previous = &result[..idx];
+        if previous
+            .iter()
+            .any(|x| x.container_name == config.container_name)
+        {
+            errors.push(exporters_error(format!(
+                "duplicate exporter name {}",
+                config.container_name
+            )));
+        }
+        if let Some(other) = previous.iter().find(|x| x.port == config.port) {
+            errors.push(exporters_error(format!(
+                "exporters {} and {} use the same port {}, please set the port field",
+                other.container_name, config.container_name, config.port
+            )));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors)
+    }
+}
+
+/// ChangeCheck is the outcome of the comparison of the exporters of a
+/// cluster before and after a change
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChangeCheck {
+    /// rejected are the changes that can't be applied to a running cluster
+    pub rejected: Vec<ParameterError>,
+
+    /// risky are the changes that are accepted, but whose effect
+    /// is probably not the expected one
+    pub risky: Vec<ParameterError>,
+}
+
+/// exporter_error creates an error for a parameter of an exporter. The errors
+/// of the entries of the exporters list are reported on the exporters
+/// parameter, like the ones of load_exporters
+pub fn exporter_error(
+    loader: &DataLoader,
+    exporter: &ExporterConfig,
+    parameter: &str,
+    message: String,
+) -> ParameterError {
+    if loader
+        .get_parameter(consts::EXPORTERS_PARAMETER_NAME)
+        .is_some()
+    {
+        ParameterError {
+            parameter: consts::EXPORTERS_PARAMETER_NAME.to_string(),
+            message: format!("({}): {}: {}", exporter.container_name, parameter, message),
+        }
+    } else {
+        ParameterError {
+            parameter: parameter.to_string(),
+            message,
+        }
+    }
+}
+
+/// load_warnings finds the parameters that are valid, but should be
+/// improved. Warnings don't block the cluster
+pub fn load_warnings(loader: &DataLoader) -> Vec<ParameterError> {
+    let mut result = Vec::new();
+
+    let databases: Vec<MonitoringDatabaseEntry> = loader
+        .get_structured_parameter(consts::MONITORING_DATABASES_PARAMETER_NAME)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    if databases.iter().any(|x| x.name() == POSTGRES_DATABASE) {
+        result.push(ParameterError {
+            parameter: consts::MONITORING_DATABASES_PARAMETER_NAME.to_string(),
+            message: format!(
+                "the {} database is reserved by CloudNativePG, the monitoring extensions are not created there",
+                POSTGRES_DATABASE
+            ),
+        });
+    }
+
+    let Ok(exporters) = load_exporters(loader) else {
+        return result;
+    };
+
+    for exporter in &exporters {
+        if !has_pinned_image(&exporter.image) {
+            result.push(exporter_error(
+                loader,
+                exporter,
+                consts::IMAGE_NAME_PARAMETER_NAME,
+                format!(
+                    "{} is not pinned, use an image catalog with tagged or digest-pinned images",
+                    exporter.image
+                ),
+            ));
+        }
+
+        if exporter
+            .resources
+            .as_ref()
+            .and_then(|x| x.limits.as_ref())
+            .is_none_or(|x| x.is_empty())
+        {
+            result.push(exporter_error(
+                loader,
+                exporter,
+                consts::RESOURCES_PARAMETER_NAME,
+                "no resource limits are set for the exporter container".to_string(),
+            ));
+        }
+    }
+
+    result
+}
+
+/// has_pinned_image tells if an image reference uses a digest or a tag
+/// other than latest
+fn has_pinned_image(image: &str) -> bool {
+    if image.contains('@') {
+        return true;
+    }
+
+    // The tag follows the last path component, which may be preceded
+    // by a registry with a port
+    let name = image.rsplit('/').next().unwrap_or(image);
+    name.split_once(':')
+        .is_some_and(|(_, tag)| !tag.is_empty() && tag != "latest")
+}
+
+/// check_change compares the exporters of a cluster before and after a
+/// change. Exporters are matched by name, and an invalid old configuration
+/// isn't compared, as it never reached the instance Pods
+pub fn check_change(old: &DataLoader, new: &DataLoader) -> ChangeCheck {
+    let mut result = ChangeCheck::default();
+    let (Ok(old_exporters), Ok(new_exporters)) = (load_exporters(old), load_exporters(new)) else {
+        return result;
+    };
+
+    let error = |exporter: &ExporterConfig, parameter: &str, message: String| {
+        exporter_error(new, exporter, parameter, message)
+    };
+
+    for old_exporter in &old_exporters {
+        let Some(new_exporter) = new_exporters
+            .iter()
+            .find(|x| x.container_name == old_exporter.container_name)
+        else {
+            result.risky.push(ParameterError {
+                parameter: consts::EXPORTERS_PARAMETER_NAME.to_string(),
+                message: format!(
+                    "exporter {} removed, the existing Pods keep running it until they are recreated",
+                    old_exporter.container_name
+                ),
+            });
+            continue;
+        };
+
+        if old_exporter.backend.name() != new_exporter.backend.name() {
+            result.rejected.push(error(
+                new_exporter,
+                consts::EXPORTER_TYPE_PARAMETER_NAME,
+                format!(
+                    "can't be changed from {} to {}, as the configuration of the running exporter is not compatible",
+                    old_exporter.backend.name(),
+                    new_exporter.backend.name()
+                ),
+            ));
+        }
+
+        if old_exporter.port != new_exporter.port {
+            result.rejected.push(error(
+                new_exporter,
+                consts::PORT_PARAMETER_NAME,
+                format!(
+                    "can't be changed from {} to {}, as it is used by the existing monitors",
+                    old_exporter.port, new_exporter.port
+                ),
+            ));
+        }
+
+        for (role, parameter) in [
+            (
+                InstanceRole::Primary,
+                consts::PRIMARY_CONFIG_MAP_PARAMETER_NAME,
+            ),
+            (
+                InstanceRole::Replica,
+                consts::REPLICA_CONFIG_MAP_PARAMETER_NAME,
+            ),
+        ] {
+            let old_name = old_exporter.config_map_name(role);
+            let new_name = new_exporter.config_map_name(role);
+            if old_name != new_name {
+                result.risky.push(error(
+                    new_exporter,
+                    parameter,
+                    format!(
+                        "changed from {} to {}, the existing Pods keep using {} until they are recreated",
+                        old_name, new_name, old_name
+                    ),
+                ));
+            }
+        }
+    }
+
+    result
+}
+
+/// parse_exporter creates the configuration of an exporter. When the
+/// exporter is an entry of the exporters list, `entry` identifies it and
+/// the errors are reported on the exporters parameter
+fn parse_exporter(
+    container_name: &str,
+    volume_name: &str,
+    port_name: &str,
+    parameters: &HashMap<String, String>,
+    entry: Option<&str>,
+) -> Result<ExporterConfig, Vec<ParameterError>> {
+    let error = |parameter: &str, message: &str| match entry {
+        None => ParameterError {
+            parameter: parameter.to_string(),
+            message: message.to_string(),
+        },
+        Some(entry) => ParameterError {
+            parameter: consts::EXPORTERS_PARAMETER_NAME.to_string(),
+            message: format!("{}: {}: {}", entry, parameter, message),
+        },
+    };
+
+    let mut errors = Vec::new();
+
+    let backend = exporter::get_backend(
+        parameters
+            .get(consts::EXPORTER_TYPE_PARAMETER_NAME)
+            .map(|x| x.as_str()),
+    );
+    if backend.is_none() {
+        errors.push(error(
+            consts::EXPORTER_TYPE_PARAMETER_NAME,
+            &format!(
+                "unknown exporter type, supported ones are: {}",
+                exporter::backend_names()
+            ),
+        ));
+    }
+
+    // The role-specific ConfigMaps fall back to the generic one
+    let config_map_name = parameters.get(consts::CONFIG_MAP_PARAMETER_NAME);
+    let primary_config_map_name = parameters
+        .get(consts::PRIMARY_CONFIG_MAP_PARAMETER_NAME)
+        .or(config_map_name)
+        .cloned();
+    let replica_config_map_name = parameters
+        .get(consts::REPLICA_CONFIG_MAP_PARAMETER_NAME)
+        .or(config_map_name)
+        .cloned();
+    if primary_config_map_name.is_none() || replica_config_map_name.is_none() {
+        errors.push(error(
+            consts::CONFIG_MAP_PARAMETER_NAME,
+            "this parameter is required",
+        ));
+    }
+
+    let port = match parameters.get(consts::PORT_PARAMETER_NAME) {
+        None => backend.map(|x| x.default_port()),
+        Some(value) => match value.parse::<u16>() {
+            Ok(port) if port > 0 => Some(port),
+            _ => {
+                errors.push(error(
+                    consts::PORT_PARAMETER_NAME,
+                    "expected a port number between 1 and 65535",
+                ));
+                None
+            }
+        },
+    };
+    if let Some(port) = port.filter(|x| RESERVED_PORTS.contains(x)) {
+        errors.push(error(
+            consts::PORT_PARAMETER_NAME,
+            &format!("port {} is already used by CloudNativePG", port),
+        ));
+    }
+
+    let image = backend.and_then(|backend| match resolve_image(backend, parameters) {
+        Ok(image) => Some(image),
+        Err(message) => {
+            errors.push(error(consts::EXPORTER_VERSION_PARAMETER_NAME, &message));
+            None
+        }
+    });
+
+    let image_pull_policy = parameters
+        .get(consts::IMAGE_PULL_POLICY_PARAMETER_NAME)
+        .cloned();
+    if let Some(policy) = &image_pull_policy {
+        if !IMAGE_PULL_POLICIES.contains(&policy.as_str()) {
+            errors.push(error(
+                consts::IMAGE_PULL_POLICY_PARAMETER_NAME,
+                &format!("expected one of: {}", IMAGE_PULL_POLICIES.join(", ")),
+            ));
+        }
+    }
+    let image_pull_secrets: Vec<String> = parse_list(
+        parameters,
+        consts::IMAGE_PULL_SECRETS_PARAMETER_NAME,
+        &mut errors,
+        &error,
+    )
+    .into_iter()
+    .map(ImagePullSecret::name)
+    .collect();
+    if image_pull_secrets.iter().any(|x| x.is_empty()) {
+        errors.push(error(
+            consts::IMAGE_PULL_SECRETS_PARAMETER_NAME,
+            "the Secret name can't be empty",
+        ));
+    }
+
+    let log_level = parameters
+        .get(consts::EXPORTER_LOG_LEVEL_PARAMETER_NAME)
+        .cloned()
+        .unwrap_or(consts::EXPORTER_LOG_LEVEL_DEFAULT.to_string());
+    if !exporter::LOG_LEVELS.contains(&log_level.as_str()) {
+        errors.push(error(
+            consts::EXPORTER_LOG_LEVEL_PARAMETER_NAME,
+            &format!(
+                "unknown log level, supported ones are: {}",
+                exporter::LOG_LEVELS.join(", ")
+            ),
+        ));
+    }
+
+    let log_format = parameters
+        .get(consts::EXPORTER_LOG_FORMAT_PARAMETER_NAME)
+        .cloned();
+    if let (Some(backend), Some(log_format)) = (backend, &log_format) {
+        if backend.log_formats().is_empty() {
+            errors.push(error(
+                consts::EXPORTER_LOG_FORMAT_PARAMETER_NAME,
+                &format!("not supported by the {} exporter type", backend.name()),
+            ));
+        } else if !backend.log_formats().contains(&log_format.as_str()) {
+            errors.push(error(
+                consts::EXPORTER_LOG_FORMAT_PARAMETER_NAME,
+                &format!(
+                    "unknown log format, supported ones are: {}",
+                    backend.log_formats().join(", ")
+                ),
+            ));
+        }
+    }
+
+    let env: Vec<api::EnvVar> =
+        parse_list(parameters, consts::ENV_PARAMETER_NAME, &mut errors, &error);
+    let env_from: Vec<api::EnvFromSource> = parse_list(
+        parameters,
+        consts::ENV_FROM_PARAMETER_NAME,
+        &mut errors,
+        &error,
+    );
+    let command: Option<Vec<String>> = parameters.get(consts::COMMAND_PARAMETER_NAME).map(|_| {
+        parse_list(
+            parameters,
+            consts::COMMAND_PARAMETER_NAME,
+            &mut errors,
+            &error,
+        )
+    });
+    let extra_args: Vec<String> =
+        parse_list(parameters, consts::ARGS_PARAMETER_NAME, &mut errors, &error);
+    let allow_env_override = parameters
+        .get(consts::ALLOW_ENV_OVERRIDE_PARAMETER_NAME)
+        .map(|x| helper::parse_bool(x))
+        .transpose()
+        .unwrap_or_else(|message| {
+            errors.push(error(consts::ALLOW_ENV_OVERRIDE_PARAMETER_NAME, &message));
+            None
+        })
+        .unwrap_or_default();
+
+    for (idx, var) in env.iter().enumerate() {
+        if var.name.is_empty() {
+            errors.push(error(
+                consts::ENV_PARAMETER_NAME,
+                &format!("[{}]: name: this field is required", idx),
+            ));
+        }
+        if env[..idx].iter().any(|x| x.name == var.name) {
+            errors.push(error(
+                consts::ENV_PARAMETER_NAME,
+                &format!("duplicate environment variable {}", var.name),
+            ));
+        }
+    }
+    for (idx, source) in env_from.iter().enumerate() {
+        if source.config_map_ref.is_some() == source.secret_ref.is_some() {
+            errors.push(error(
+                consts::ENV_FROM_PARAMETER_NAME,
+                &format!("[{}]: expected either configMapRef or secretRef", idx),
+            ));
+        }
+    }
+
+    let volumes: Vec<api::Volume> = parse_list(
+        parameters,
+        consts::VOLUMES_PARAMETER_NAME,
+        &mut errors,
+        &error,
+    );
+    let volume_mounts: Vec<api::VolumeMount> = parse_list(
+        parameters,
+        consts::VOLUME_MOUNTS_PARAMETER_NAME,
+        &mut errors,
+        &error,
+    );
+    let scratch_data_mount = match parameters.get(consts::SCRATCH_DATA_MOUNT_PARAMETER_NAME) {
+        None => ScratchDataMount::Socket,
+        Some(value) => ScratchDataMount::parse(value).unwrap_or_else(|| {
+            errors.push(error(
+                consts::SCRATCH_DATA_MOUNT_PARAMETER_NAME,
+                &format!("expected one of: {}", ScratchDataMount::VALUES.join(", ")),
+            ));
+            ScratchDataMount::Socket
+        }),
+    };
+
+    let resources: Option<api::ResourceRequirements> = parameters
+        .get(consts::RESOURCES_PARAMETER_NAME)
+        .map(|value| helper::parse_structured_value(value))
+        .transpose()
+        .unwrap_or_else(|message| {
+            errors.push(error(consts::RESOURCES_PARAMETER_NAME, &message));
+            None
+        });
+
+    let security_context: Option<api::SecurityContext> = parameters
+        .get(consts::SECURITY_CONTEXT_PARAMETER_NAME)
+        .map(|value| helper::parse_structured_value(value))
+        .transpose()
+        .unwrap_or_else(|message| {
+            errors.push(error(consts::SECURITY_CONTEXT_PARAMETER_NAME, &message));
+            None
+        });
+
+    for message in validate_volumes(volume_name, &volumes) {
+        errors.push(error(consts::VOLUMES_PARAMETER_NAME, &message));
+    }
+    for message in validate_volume_mounts(scratch_data_mount, &volumes, &volume_mounts) {
+        errors.push(error(consts::VOLUME_MOUNTS_PARAMETER_NAME, &message));
+    }
+
+    match (
+        backend,
+        image,
+        primary_config_map_name,
+        replica_config_map_name,
+        port,
+    ) {
+        (
+            Some(backend),
+            Some(image),
+            Some(primary_config_map_name),
+            Some(replica_config_map_name),
+            Some(port),
+        ) if errors.is_empty() => {
+            let config = ExporterConfig {
+                container_name: container_name.to_string(),
+                volume_name: volume_name.to_string(),
+                port_name: port_name.to_string(),
+                backend,
+                image,
+                image_pull_policy,
+                image_pull_secrets,
+                primary_config_map_name,
+                replica_config_map_name,
+                port,
+                log_level,
+                log_format,
+                env,
+                env_from,
+                command,
+                extra_args,
+                volumes,
+                volume_mounts,
+                scratch_data_mount,
+                resources,
+                security_context,
+            };
+
+            // The exporter relies on the variables set by the plugin, which
+            // can only be replaced when explicitly allowed
+            if !allow_env_override {
+                let plugin_env = config.plugin_env(InstanceRole::Primary);
+                for var in &config.env {
+                    if plugin_env.iter().any(|x| x.name == var.name) {
+                        errors.push(error(
+                            consts::ENV_PARAMETER_NAME,
+                            &format!(
+                                "{} is set by the plugin, use {} to replace it",
+                                var.name,
+                                consts::ALLOW_ENV_OVERRIDE_PARAMETER_NAME
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            if errors.is_empty() {
+                Ok(config)
+            } else {
+                Err(errors)
+            }
+        }
+        _ => Err(errors),
+    }
+}
+
+/// resolve_image finds the image of an exporter. The exporterVersion
+/// parameter selects it from the image catalog, taking precedence over
+/// imageName. The catalog default is used when neither is specified
+pub fn resolve_image(
+    backend: &dyn ExporterBackend,
+    parameters: &HashMap<String, String>,
+) -> Result<String, String> {
+    if let Some(version) = parameters.get(consts::EXPORTER_VERSION_PARAMETER_NAME) {
+        return catalog::get()
+            .image(backend.name(), version)
+            .map(|x| x.to_string())
+            .ok_or_else(|| {
+                format!(
+                    "unknown version of the {} exporter type, known ones are: {}",
+                    backend.name(),
+                    catalog::get().versions(backend.name())
+                )
+            });
+    }
+
+    Ok(parameters
+        .get(consts::IMAGE_NAME_PARAMETER_NAME)
+        .cloned()
+        .or_else(|| {
+            catalog::get()
+                .default_image(backend.name())
+                .map(|x| x.to_string())
+        })
+        .unwrap_or(backend.default_image().to_string()))
+}
+
+/// parse_list decodes a parameter containing a list, recording an error
+/// when the value is not valid. A missing parameter is an empty list
+fn parse_list<T: DeserializeOwned>(
+    parameters: &HashMap<String, String>,
+    parameter: &str,
+    errors: &mut Vec<ParameterError>,
+    error: &dyn Fn(&str, &str) -> ParameterError,
+) -> Vec<T> {
+    let Some(value) = parameters.get(parameter) else {
+        return Vec::new();
+    };
+
+    helper::parse_structured_value(value).unwrap_or_else(|message| {
+        errors.push(error(parameter, &format!("expected a list, {}", message)));
+        Vec::new()
+    })
+}
+
+/// validate_volumes checks that the additional volumes of an exporter
+/// have a supported source and don't clash with the plugin ones
+fn validate_volumes(config_volume_name: &str, volumes: &[api::Volume]) -> Vec<String> {
+    let mut result = Vec::new();
+    for (idx, volume) in volumes.iter().enumerate() {
+        if volume.name.is_empty() {
+            result.push(format!("[{}]: name: this field is required", idx));
+        } else if [
+            config_volume_name,
+            exporter::PODINFO_VOLUME_NAME,
+            exporter::SCRATCH_DATA_VOLUME_NAME,
+        ]
+        .contains(&volume.name.as_str())
+        {
+            result.push(format!("{} is a volume used by the plugin", volume.name));
+        } else if volumes[..idx].iter().any(|x| x.name == volume.name) {
+            result.push(format!("duplicate volume {}", volume.name));
+        }
+
+        let sources = [
+            volume.config_map.is_some(),
+            volume.secret.is_some(),
+            volume.projected.is_some(),
+            volume.empty_dir.is_some(),
+        ];
+        // A volume is serialized as its name and its source, so any
+        // other field is an unsupported source
+        let other_source = serde_json::to_value(volume)
+            .ok()
+            .and_then(|x| x.as_object().map(|x| x.len()))
+            .unwrap_or_default()
+            > 2;
+        if sources.iter().filter(|x| **x).count() != 1 || other_source {
+            result.push(format!(
+                "[{}]: expected exactly one of configMap, secret, projected or emptyDir",
+                idx
+            ));
+        }
+    }
+    result
+}
+
+/// validate_volume_mounts checks that the additional mounts of an exporter
+/// refer to its additional volumes and don't overlap the plugin ones
+fn validate_volume_mounts(
+    scratch_data_mount: ScratchDataMount,
+    volumes: &[api::Volume],
+    volume_mounts: &[api::VolumeMount],
+) -> Vec<String> {
+    let overlaps = |a: &str, b: &str| {
+        let (a, b) = (a.trim_end_matches('/'), b.trim_end_matches('/'));
+        a == b || a.starts_with(&format!("{}/", b)) || b.starts_with(&format!("{}/", a))
+    };
+
+    let mut result = Vec::new();
+    for (idx, mount) in volume_mounts.iter().enumerate() {
+        if !volumes.iter().any(|x| x.name == mount.name) {
+            result.push(format!(
+                "[{}]: {} is not one of the additional volumes",
+                idx, mount.name
+            ));
+        }
+
+        let path = &mount.mount_path;
+        if !path.starts_with('/') {
+            result.push(format!("[{}]: mountPath: expected an absolute path", idx));
+        } else if let Some(reserved) = [exporter::CONFIG_DIRECTORY, exporter::PODINFO_DIRECTORY]
+            .iter()
+            .chain(scratch_data_mount.mount_paths())
+            .find(|x| overlaps(path, x))
+        {
+            result.push(format!(
+                "{} conflicts with {}, which is used by the plugin",
+                path, reserved
+            ));
+        } else if let Some(other) = volume_mounts[..idx]
+            .iter()
+            .find(|x| overlaps(path, &x.mount_path))
+        {
+            result.push(format!("{} conflicts with {}", path, other.mount_path));
+        }
+    }
+    result
+}
+
+/// validate_resource_name checks that a name can be used for a Kubernetes
+/// resource, following the DNS subdomain rules
+fn validate_resource_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_RESOURCE_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+        && name.split('.').all(|x| {
+            x.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && x.ends_with(|c: char| c.is_ascii_alphanumeric())
+        });
+    if !valid {
+        return Err(format!(
+            "expected at most {} lowercase alphanumeric characters, '-' or '.', starting and ending with an alphanumeric character",
+            MAX_RESOURCE_NAME_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+/// validate_exporter_name checks that an exporter name can be used
+/// as the name of a container and of a port
+fn validate_exporter_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_EXPORTER_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && !name.ends_with('-')
+        && !name.contains("--");
+    if !valid {
+        return Err(format!(
+            "expected at most {} lowercase alphanumeric characters or '-', starting with a letter",
+            MAX_EXPORTER_NAME_LENGTH
+        ));
+    }
+
+    if RESERVED_CONTAINER_NAMES.contains(&name) {
+        return Err(format!("{} is already used by CloudNativePG", name));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_exporter() {
+        let exporters = load_exporters(&DataLoader::from_parameters(
+            serde_json::json!({"configMapName": "config"}),
+        ))
+        .unwrap();
+
+        assert_eq!(exporters.len(), 1);
+        assert_eq!(exporters[0].container_name, "sql-exporter");
+        assert_eq!(exporters[0].volume_name, "sql-exporter-configuration");
+        assert_eq!(exporters[0].port, 9237);
+    }
+
+    #[test]
+    fn test_role_config_maps() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "replicaConfigMapName": "replica-config"
+        })))
+        .unwrap();
+
+        assert_eq!(
+            exporters[0].config_map_name(InstanceRole::Primary),
+            "config"
+        );
+        assert_eq!(
+            exporters[0].config_map_name(InstanceRole::Replica),
+            "replica-config"
+        );
+    }
+
+    #[test]
+    fn test_role_config_maps_without_default() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "primaryConfigMapName": "primary-config",
+            "replicaConfigMapName": "replica-config"
+        })))
+        .unwrap();
+        assert_eq!(
+            exporters[0].config_map_name(InstanceRole::Primary),
+            "primary-config"
+        );
+
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "primaryConfigMapName": "primary-config"
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(errors[0].parameter, "configMapName");
+    }
+
+    #[test]
+    fn test_single_exporter_missing_config_map() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({})))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            errors,
+            vec![ParameterError {
+                parameter: "configMapName".to_string(),
+                message: "this parameter is required".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_multiple_exporters() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "imageName": "myregistry/sql_exporter:1.0",
+            "exporters": r#"
+- name: business
+  configMapName: business-queries
+- name: infra
+  configMapName: infra-queries
+  exporterType: burningalchemist-sql-exporter
+  imageName: myregistry/burningalchemist:1.0
+  port: 9400
+"#
+        })))
+        .unwrap();
+
+        assert_eq!(exporters.len(), 2);
+        assert_eq!(exporters[0].container_name, "business");
+        assert_eq!(exporters[0].volume_name, "business-configuration");
+        assert_eq!(exporters[0].image, "myregistry/sql_exporter:1.0");
+        assert_eq!(exporters[0].port, 9237);
+        assert_eq!(
+            exporters[1].config_map_name(InstanceRole::Primary),
+            "infra-queries"
+        );
+        assert_eq!(exporters[1].image, "myregistry/burningalchemist:1.0");
+        assert_eq!(exporters[1].port, 9400);
+    }
+
+    #[test]
+    fn test_multiple_exporters_collisions() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "queries",
+            "exporters": r#"[{"name": "one"}, {"name": "one", "port": 9300}, {"name": "two"}]"#
+        })))
+        .err()
+        .unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].message.contains("duplicate exporter name one"));
+        assert!(errors[1].message.contains("same port 9237"));
+    }
+
+    #[test]
+    fn test_multiple_exporters_invalid_entries() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "exporters": r#"[{"configMapName": "x"}, {"name": "Wrong"}, {"name": "ok", "port": 9187}]"#
+        })))
+        .err()
+        .unwrap();
+
+        assert_eq!(errors.len(), 4);
+        assert!(errors.iter().all(|x| x.parameter == "exporters"));
+        assert_eq!(errors[0].message, "[0]: name: this field is required");
+        assert!(errors[2]
+            .message
+            .starts_with("[2] (ok): configMapName: this parameter is required"));
+        assert!(errors[3].message.contains("port 9187 is already used"));
+    }
+
+    #[test]
+    fn test_invalid_exporters_list() {
+        let errors = load_exporters(&DataLoader::from_parameters(
+            serde_json::json!({"exporters": "wrong"}),
+        ))
+        .err()
+        .unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("expected a list of exporter definitions"));
+    }
+
+    #[test]
+    fn test_structured_exporters_list() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "queries",
+            "exporters": [
+                {"name": "one", "port": 9300},
+                {"name": "two", "port": 9301}
+            ]
+        })))
+        .unwrap();
+
+        assert_eq!(exporters.len(), 2);
+        assert_eq!(exporters[0].port, 9300);
+        assert_eq!(exporters[1].port, 9301);
+
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "queries",
+            "exporters": [{"name": "one", "imageName": null}]
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(
+            errors[0].message,
+            "[0]: imageName: null is not a valid value"
+        );
+    }
+
+    #[test]
+    fn test_mixed_exporter_types() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "queries",
+            "imageName": "registry.example.com/sql_exporter:0.5",
+            "exporters": [
+                {"name": "business"},
+                {"name": "infra", "exporterType": "postgres-exporter"},
+                {
+                    "name": "custom",
+                    "exporterType": "burningalchemist-sql-exporter",
+                    "imageName": "registry.example.com/burningalchemist:0.14.3"
+                }
+            ]
+        })))
+        .unwrap();
+
+        assert_eq!(exporters[0].image, "registry.example.com/sql_exporter:0.5");
+        assert_eq!(
+            exporters[1].image,
+            "quay.io/prometheuscommunity/postgres-exporter:v0.15.0"
+        );
+        assert_eq!(
+            exporters[2].image,
+            "registry.example.com/burningalchemist:0.14.3"
+        );
+
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "queries",
+            "exporterType": "postgres-exporter",
+            "exporterVersion": "0.15.0",
+            "exporters": [
+                {"name": "infra"},
+                {"name": "business", "exporterType": "justwatchcom-sql-exporter", "port": 9237}
+            ]
+        })))
+        .unwrap();
+
+        assert_eq!(
+            exporters[0].image,
+            "quay.io/prometheuscommunity/postgres-exporter:v0.15.0"
+        );
+        assert_eq!(exporters[1].image, consts::IMAGE_NAME_PARAMETER_DEFAULT);
+    }
+
+    #[test]
+    fn test_extra_env_and_args() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "env": [
+                {"name": "TZ", "value": "UTC"},
+                {"name": "PGPASSWORD", "valueFrom": {"secretKeyRef": {"name": "app", "key": "password"}}}
+            ],
+            "envFrom": "- configMapRef:\n    name: exporter-env\n",
+            "command": ["/bin/sql_exporter"],
+            "args": ["-debug"]
+        })))
+        .unwrap();
+
+        let env = exporters[0].env(InstanceRole::Primary);
+        assert!(env.iter().any(|x| x.name == "CONFIG"));
+        assert_eq!(env.last().unwrap().name, "PGPASSWORD");
+        assert_eq!(exporters[0].env_from.len(), 1);
+        assert_eq!(
+            exporters[0].command.as_deref(),
+            Some(&["/bin/sql_exporter".to_string()][..])
+        );
+        assert_eq!(exporters[0].args(), vec!["-debug"]);
+    }
+
+    #[test]
+    fn test_extra_env_override() {
+        let parameters = serde_json::json!({
+            "configMapName": "config",
+            "env": [{"name": "LOGLEVEL", "value": "debug"}]
+        });
+        let errors = load_exporters(&DataLoader::from_parameters(parameters.clone()))
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].parameter, "env");
+        assert!(errors[0]
+            .message
+            .starts_with("LOGLEVEL is set by the plugin"));
+
+        let mut parameters = parameters;
+        parameters["allowEnvOverride"] = serde_json::json!(true);
+        let exporters = load_exporters(&DataLoader::from_parameters(parameters)).unwrap();
+        let env = exporters[0].env(InstanceRole::Primary);
+        let loglevel: Vec<_> = env.iter().filter(|x| x.name == "LOGLEVEL").collect();
+        assert_eq!(loglevel.len(), 1);
+        assert_eq!(loglevel[0].value.as_deref(), Some("debug"));
+    }
+
+    #[test]
+    fn test_invalid_extra_env() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "env": "wrong",
+            "envFrom": [{"prefix": "X_"}],
+            "allowEnvOverride": "maybe"
+        })))
+        .err()
+        .unwrap();
+
+        let parameters: Vec<_> = errors.iter().map(|x| x.parameter.as_str()).collect();
+        assert_eq!(parameters, vec!["env", "allowEnvOverride", "envFrom"]);
+    }
+
+    #[test]
+    fn test_log_settings() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporterType": "burningalchemist-sql-exporter",
+            "exporterLogLevel": "debug",
+            "exporterLogFormat": "json"
+        })))
+        .unwrap();
+
+        let args = exporters[0].args();
+        assert!(args.contains(&"--log.level=debug".to_string()));
+        assert!(args.contains(&"--log.format=json".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_log_settings() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporterLogLevel": "verbose",
+            "exporterLogFormat": "json"
+        })))
+        .err()
+        .unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].parameter, "exporterLogLevel");
+        assert_eq!(
+            errors[1].message,
+            "not supported by the justwatchcom-sql-exporter exporter type"
+        );
+    }
+
+    #[test]
+    fn test_image_pull_settings() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imagePullPolicy": "Always",
+            "imagePullSecrets": ["registry", {"name": "mirror"}]
+        })))
+        .unwrap();
+
+        assert_eq!(exporters[0].image_pull_policy.as_deref(), Some("Always"));
+        assert_eq!(exporters[0].image_pull_secrets, vec!["registry", "mirror"]);
+
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imagePullPolicy": "always",
+            "imagePullSecrets": "registry"
+        })))
+        .err()
+        .unwrap();
+        let parameters: Vec<_> = errors.iter().map(|x| x.parameter.as_str()).collect();
+        assert_eq!(parameters, vec!["imagePullPolicy", "imagePullSecrets"]);
+    }
+
+    #[test]
+    fn test_exporter_version() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imageName": "myregistry/sql_exporter:1.0",
+            "exporterVersion": "0.5"
+        })))
+        .unwrap();
+        assert_eq!(exporters[0].image, consts::IMAGE_NAME_PARAMETER_DEFAULT);
+
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporterType": "burningalchemist-sql-exporter",
+            "exporterVersion": "0.14.3"
+        })))
+        .unwrap();
+        assert_eq!(
+            exporters[0].image,
+            "docker.io/burningalchemist/sql_exporter:0.14.3"
+        );
+
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporterVersion": "0.0"
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(errors[0].parameter, "exporterVersion");
+    }
+
+    #[test]
+    fn test_extra_volumes() {
+        let exporters = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "volumes": [
+                {"name": "ca", "secret": {"secretName": "foreign-ca"}},
+                {"name": "queries", "configMap": {"name": "queries"}}
+            ],
+            "volumeMounts": [
+                {"name": "ca", "mountPath": "/etc/ssl/foreign", "readOnly": true},
+                {"name": "queries", "mountPath": "/queries"}
+            ]
+        })))
+        .unwrap();
+
+        assert_eq!(exporters[0].volumes.len(), 2);
+        assert_eq!(exporters[0].volume_mounts[1].mount_path, "/queries");
+    }
+
+    #[test]
+    fn test_invalid_extra_volumes() {
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "volumes": [
+                {"name": "scratch-data", "emptyDir": {}},
+                {"name": "host", "hostPath": {"path": "/"}},
+                {"name": "queries", "configMap": {"name": "queries"}}
+            ],
+            "volumeMounts": [
+                {"name": "queries", "mountPath": "/config/queries"},
+                {"name": "queries", "mountPath": "/queries"},
+                {"name": "queries", "mountPath": "/queries/"},
+                {"name": "missing", "mountPath": "/missing"}
+            ]
+        })))
+        .err()
+        .unwrap();
+
+        let messages: Vec<_> = errors.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "scratch-data is a volume used by the plugin",
+                "[1]: expected exactly one of configMap, secret, projected or emptyDir",
+                "/config/queries conflicts with /config, which is used by the plugin",
+                "/queries/ conflicts with /queries",
+                "[3]: missing is not one of the additional volumes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scratch_data_mount() {
+        let load = |parameters: serde_json::Value| {
+            load_exporters(&DataLoader::from_parameters(parameters))
+                .map(|x| x[0].scratch_data_mount)
+        };
+
+        assert_eq!(
+            load(serde_json::json!({"configMapName": "config"})),
+            Ok(ScratchDataMount::Socket)
+        );
+        assert_eq!(
+            load(serde_json::json!({"configMapName": "config", "scratchDataMount": "full"})),
+            Ok(ScratchDataMount::Full)
+        );
+        assert!(load(serde_json::json!({
+            "configMapName": "config",
+            "volumes": [{"name": "run", "emptyDir": {}}],
+            "volumeMounts": [{"name": "run", "mountPath": "/run"}]
+        }))
+        .is_ok());
+        assert!(load(serde_json::json!({
+            "configMapName": "config",
+            "scratchDataMount": "everything"
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_scrape_settings() {
+        let settings = load_scrape_settings(&DataLoader::from_parameters(serde_json::json!({
+            "createService": "true"
+        })))
+        .unwrap();
+        assert!(settings.service);
+
+        let errors = load_scrape_settings(&DataLoader::from_parameters(serde_json::json!({
+            "createService": "sometimes"
+        })))
+        .unwrap_err();
+        assert_eq!(errors[0].parameter, "createService");
+    }
+
+    #[test]
+    fn test_network_policy_settings() {
+        let settings = load_scrape_settings(&DataLoader::from_parameters(serde_json::json!({
+            "networkPolicy": "namespaceSelector: {matchLabels: {kubernetes.io/metadata.name: monitoring}}"
+        })))
+        .unwrap();
+        let selector = settings.network_policy.unwrap().namespace_selector.unwrap();
+        assert_eq!(
+            selector.match_labels.unwrap()["kubernetes.io/metadata.name"],
+            "monitoring"
+        );
+
+        let errors = load_scrape_settings(&DataLoader::from_parameters(serde_json::json!({
+            "networkPolicy": "{}"
+        })))
+        .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "namespaceSelector or podSelector is required"
+        );
+    }
+
+    #[test]
+    fn test_check_change() {
+        let old = DataLoader::from_parameters(serde_json::json!({"configMapName": "config"}));
+
+        let check = check_change(
+            &old,
+            &DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "exporterType": "postgres-exporter",
+                "port": "9300"
+            })),
+        );
+        assert_eq!(
+            check
+                .rejected
+                .iter()
+                .map(|x| x.parameter.as_str())
+                .collect::<Vec<_>>(),
+            vec!["exporterType", "port"]
+        );
+        assert!(check.risky.is_empty());
+
+        let check = check_change(
+            &old,
+            &DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "replicaConfigMapName": "replica-config"
+            })),
+        );
+        assert!(check.rejected.is_empty());
+        assert_eq!(
+            check.risky,
+            vec![ParameterError {
+                parameter: "replicaConfigMapName".to_string(),
+                message: "changed from config to replica-config, the existing Pods keep using config until they are recreated".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_change_exporters_list() {
+        let old = DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporters": r#"[{"name": "one"}, {"name": "two", "port": "9300"}]"#
+        }));
+        let new = DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "exporters": r#"[{"name": "one", "port": "9400"}]"#
+        }));
+
+        let check = check_change(&old, &new);
+        assert_eq!(
+            check.rejected,
+            vec![ParameterError {
+                parameter: "exporters".to_string(),
+                message: "(one): port: can't be changed from 9237 to 9400, as it is used by the existing monitors".to_string(),
+            }]
+        );
+        assert_eq!(check.risky.len(), 1);
+        assert_eq!(check.risky[0].parameter, "exporters");
+    }
+
+    #[test]
+    fn test_warnings() {
+        let warnings = load_warnings(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config"
+        })));
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|x| x.parameter.as_str())
+                .collect::<Vec<_>>(),
+            vec!["resources"]
+        );
+
+        let warnings = load_warnings(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imageName": "ghcr.io/justwatchcom/sql_exporter:latest"
+        })));
+        assert_eq!(warnings[0].parameter, "imageName");
+
+        let warnings = load_warnings(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imageName": "registry.example.com:5000/sql_exporter:0.5",
+            "resources": "limits: {memory: 64Mi}"
+        })));
+        assert!(warnings.is_empty());
+
+        let warnings = load_warnings(&DataLoader::from_parameters(serde_json::json!({
+            "monitoringDatabases": ["postgres"]
+        })));
+        assert_eq!(warnings[0].parameter, "monitoringDatabases");
+
+        let errors = load_exporters(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "resources": "[]"
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(errors[0].parameter, "resources");
+    }
+
+    #[test]
+    fn test_has_pinned_image() {
+        assert!(!has_pinned_image(
+            "ghcr.io/justwatchcom/sql_exporter:latest"
+        ));
+        assert!(!has_pinned_image("registry.example.com:5000/sql_exporter"));
+        assert!(has_pinned_image(
+            "registry.example.com/sql_exporter@sha256:0123"
+        ));
+        assert!(has_pinned_image("sql_exporter:0.5"));
+    }
+
+    #[test]
+    fn test_monitoring_settings() {
+        let settings = load_monitoring_settings(&DataLoader::from_parameters(serde_json::json!({
+            "enableStatStatements": true
+        })))
+        .unwrap();
+        assert!(settings.stat_statements);
+        assert!(!settings.track_io_timing);
+        assert_eq!(settings.extensions, vec!["pg_stat_statements"]);
+        assert!(settings.databases.is_empty());
+
+        let errors = load_monitoring_settings(&DataLoader::from_parameters(serde_json::json!({
+            "enableTrackIoTiming": "yes"
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(errors[0].parameter, "enableTrackIoTiming");
+    }
+
+    #[test]
+    fn test_monitoring_databases() {
+        let settings = load_monitoring_settings(&DataLoader::from_parameters(serde_json::json!({
+            "monitoringDatabases": ["app", "postgres", {"name": "sales"}],
+            "monitoringExtensions": ["pg_stat_statements", "pg_buffercache"]
+        })))
+        .unwrap();
+
+        assert_eq!(
+            settings.databases,
+            vec![
+                MonitoringDatabase {
+                    name: "app".to_string()
+                },
+                MonitoringDatabase {
+                    name: "sales".to_string()
+                },
+            ]
+        );
+        assert_eq!(settings.extensions.len(), 2);
+
+        let errors = load_monitoring_settings(&DataLoader::from_parameters(serde_json::json!({
+            "monitoringDatabases": [{"name": "sales", "owner": "sales"}, "app", "app"]
+        })))
+        .err()
+        .unwrap();
+        let messages: Vec<_> = errors.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[0] (sales): owner: the plugin doesn't manage the databases, so their owner can't be set",
+                "duplicate database app"
+            ]
+        );
+
+        let errors = load_monitoring_settings(&DataLoader::from_parameters(serde_json::json!({
+            "monitoringDatabases": [
+                "My_Db",
+                "my-db",
+                "sales data"
+            ]
+        })))
+        .err()
+        .unwrap();
+        let messages: Vec<_> = errors.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            "[1] (my-db): the name of its Database resource, cluster-example-monitoring-my-db, is already used by database My_Db"
+        );
+        assert!(messages[1].starts_with(
+            "[2] (sales data): the name of its Database resource, cluster-example-monitoring-sales data, is not valid"
+        ));
+    }
+
+    #[test]
+    fn test_validate_resource_name() {
+        assert!(validate_resource_name("cluster-example-monitoring-app").is_ok());
+        assert!(validate_resource_name("cluster.example-1").is_ok());
+        assert!(validate_resource_name("cluster-example-monitoring-").is_err());
+        assert!(validate_resource_name("cluster..example").is_err());
+        assert!(validate_resource_name("Cluster").is_err());
+        assert!(validate_resource_name(&"a".repeat(254)).is_err());
+    }
+}
@@ -1,11 +1,226 @@
 /// PLUGIN_NAME is the name of this plugin
 pub const PLUGIN_NAME: &str = "plugin-generic-exporter.leonardoce.io";
 
+/// VERSION is the version of this plugin, including the commit and the day
+/// it has been built
+pub const VERSION: &str = env!("BUILD_VERSION");
+
+/// GIT_COMMIT is the commit this plugin has been built from
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+/// BUILD_DATE is the date when this plugin has been built
+pub const BUILD_DATE: &str = env!("BUILD_DATE");
+
+/// MINIMUM_CNPG_VERSION is the first CloudNativePG version supporting
+/// the CNPG-i interface used by this plugin
+pub const MINIMUM_CNPG_VERSION: &str = "1.25.0";
+
+/// SIDECAR_MODES are the ways the exporter can be added to the instance Pods.
+/// The exporter is injected as a native sidecar, i.e. an init container
+/// with an `Always` restart policy
+pub const SIDECAR_MODES: &[&str] = &["initContainer"];
+
 /// IMAGE_NAME_PARAMETER_NAME is the name of the image parameter
 pub const IMAGE_NAME_PARAMETER_NAME: &str = "imageName";
 
 /// IMAGE_NAME_PARAMETER_DEFAULT is the default image name used for the generic SQL exporter
-pub const IMAGE_NAME_PARAMETER_DEFAULT: &str = "ghcr.io/justwatchcom/sql_exporter:latest";
+pub const IMAGE_NAME_PARAMETER_DEFAULT: &str = "ghcr.io/justwatchcom/sql_exporter:v0.5";
+
+/// EXPORTER_VERSION_PARAMETER_NAME is the name of the parameter selecting
+/// the exporter image from the image catalog
+pub const EXPORTER_VERSION_PARAMETER_NAME: &str = "exporterVersion";
+
+/// IMAGE_PULL_POLICY_PARAMETER_NAME is the name of the parameter containing
+/// the pull policy of the exporter image
+pub const IMAGE_PULL_POLICY_PARAMETER_NAME: &str = "imagePullPolicy";
+
+/// IMAGE_PULL_SECRETS_PARAMETER_NAME is the name of the parameter containing
+/// the list of the Secrets used to pull the exporter image
+pub const IMAGE_PULL_SECRETS_PARAMETER_NAME: &str = "imagePullSecrets";
 
 /// CONFIG_MAP_PARAMETER_NAME is the name of the configmap name parameter
 pub const CONFIG_MAP_PARAMETER_NAME: &str = "configMapName";
+
+/// PRIMARY_CONFIG_MAP_PARAMETER_NAME is the name of the parameter containing
+/// the ConfigMap used by the exporter running in the primary instance
+pub const PRIMARY_CONFIG_MAP_PARAMETER_NAME: &str = "primaryConfigMapName";
+
+/// REPLICA_CONFIG_MAP_PARAMETER_NAME is the name of the parameter containing
+/// the ConfigMap used by the exporter running in the replicas
+pub const REPLICA_CONFIG_MAP_PARAMETER_NAME: &str = "replicaConfigMapName";
+
+/// EXPORTER_TYPE_PARAMETER_NAME is the name of the parameter selecting
+/// the exporter implementation
+pub const EXPORTER_TYPE_PARAMETER_NAME: &str = "exporterType";
+
+/// PORT_PARAMETER_NAME is the name of the parameter containing the port
+/// where the exporter serves its metrics
+pub const PORT_PARAMETER_NAME: &str = "port";
+
+/// EXPORTERS_PARAMETER_NAME is the name of the parameter containing the
+/// list of the exporters to be injected, as a JSON or YAML document
+pub const EXPORTERS_PARAMETER_NAME: &str = "exporters";
+
+/// EXPORTER_NAME_FIELD is the field containing the name of an entry
+/// of the exporters list
+pub const EXPORTER_NAME_FIELD: &str = "name";
+
+/// EXPORTER_LOG_LEVEL_PARAMETER_NAME is the name of the parameter containing
+/// the verbosity of the exporter
+pub const EXPORTER_LOG_LEVEL_PARAMETER_NAME: &str = "exporterLogLevel";
+
+/// EXPORTER_LOG_LEVEL_DEFAULT is the verbosity of the exporter when the
+/// exporterLogLevel parameter is not specified
+pub const EXPORTER_LOG_LEVEL_DEFAULT: &str = "info";
+
+/// EXPORTER_LOG_FORMAT_PARAMETER_NAME is the name of the parameter containing
+/// the format of the exporter logs
+pub const EXPORTER_LOG_FORMAT_PARAMETER_NAME: &str = "exporterLogFormat";
+
+/// ENV_PARAMETER_NAME is the name of the parameter containing the list of
+/// the additional environment variables of the exporter container
+pub const ENV_PARAMETER_NAME: &str = "env";
+
+/// ENV_FROM_PARAMETER_NAME is the name of the parameter containing the list
+/// of the sources of environment variables of the exporter container
+pub const ENV_FROM_PARAMETER_NAME: &str = "envFrom";
+
+/// ALLOW_ENV_OVERRIDE_PARAMETER_NAME is the name of the parameter allowing
+/// the additional environment variables to replace the ones set by the plugin
+pub const ALLOW_ENV_OVERRIDE_PARAMETER_NAME: &str = "allowEnvOverride";
+
+/// COMMAND_PARAMETER_NAME is the name of the parameter replacing the
+/// entrypoint of the exporter image
+pub const COMMAND_PARAMETER_NAME: &str = "command";
+
+/// ARGS_PARAMETER_NAME is the name of the parameter containing the
+/// arguments appended to the ones computed by the plugin
+pub const ARGS_PARAMETER_NAME: &str = "args";
+
+/// VOLUMES_PARAMETER_NAME is the name of the parameter containing the list
+/// of the additional volumes used by the exporter container
+pub const VOLUMES_PARAMETER_NAME: &str = "volumes";
+
+/// VOLUME_MOUNTS_PARAMETER_NAME is the name of the parameter containing the
+/// list of the additional mounts of the exporter container
+pub const VOLUME_MOUNTS_PARAMETER_NAME: &str = "volumeMounts";
+
+/// SCRATCH_DATA_MOUNT_PARAMETER_NAME is the name of the parameter selecting
+/// how the CNPG scratch-data volume is mounted in the exporter container
+pub const SCRATCH_DATA_MOUNT_PARAMETER_NAME: &str = "scratchDataMount";
+
+/// RESOURCES_PARAMETER_NAME is the name of the parameter containing the
+/// compute resources of the exporter container
+pub const RESOURCES_PARAMETER_NAME: &str = "resources";
+
+/// SECURITY_CONTEXT_PARAMETER_NAME is the name of the parameter containing
+/// the security context of the exporter container
+pub const SECURITY_CONTEXT_PARAMETER_NAME: &str = "securityContext";
+
+/// ENABLE_STAT_STATEMENTS_PARAMETER_NAME is the name of the parameter adding
+/// pg_stat_statements to the libraries preloaded by PostgreSQL
+pub const ENABLE_STAT_STATEMENTS_PARAMETER_NAME: &str = "enableStatStatements";
+
+/// ENABLE_TRACK_IO_TIMING_PARAMETER_NAME is the name of the parameter
+/// enabling the track_io_timing PostgreSQL setting
+pub const ENABLE_TRACK_IO_TIMING_PARAMETER_NAME: &str = "enableTrackIoTiming";
+
+/// MONITORING_DATABASES_PARAMETER_NAME is the name of the parameter containing
+/// the list of the databases where the monitoring extensions are created
+pub const MONITORING_DATABASES_PARAMETER_NAME: &str = "monitoringDatabases";
+
+/// MONITORING_EXTENSIONS_PARAMETER_NAME is the name of the parameter containing
+/// the list of the extensions created in the monitoring databases
+pub const MONITORING_EXTENSIONS_PARAMETER_NAME: &str = "monitoringExtensions";
+
+/// MONITORING_EXTENSIONS_DEFAULT are the extensions created in the monitoring
+/// databases when the monitoringExtensions parameter is not specified
+pub const MONITORING_EXTENSIONS_DEFAULT: &[&str] = &["pg_stat_statements"];
+
+/// CREATE_SERVICE_PARAMETER_NAME is the name of the parameter enabling the
+/// creation of a headless Service exposing the exporters
+pub const CREATE_SERVICE_PARAMETER_NAME: &str = "createService";
+
+/// SCRAPE_ANNOTATIONS_PARAMETER_NAME is the name of the parameter enabling
+/// the Prometheus scrape annotations on the instance Pods
+pub const SCRAPE_ANNOTATIONS_PARAMETER_NAME: &str = "scrapeAnnotations";
+
+/// SCRAPE_ANNOTATION_PREFIX is the prefix of the annotations used by
+/// Prometheus annotation-based discovery
+pub const SCRAPE_ANNOTATION_PREFIX: &str = "prometheus.io/";
+
+/// NETWORK_POLICY_PARAMETER_NAME is the name of the parameter selecting the
+/// Pods allowed to reach the exporters through a NetworkPolicy
+pub const NETWORK_POLICY_PARAMETER_NAME: &str = "networkPolicy";
+
+/// MATERIALIZE_DEFAULTS_PARAMETER_NAME is the name of the parameter allowing
+/// a cluster to opt out of having the plugin defaults written in its parameters
+pub const MATERIALIZE_DEFAULTS_PARAMETER_NAME: &str = "materializeDefaults";
+
+/// SUPPORTED_PARAMETERS is the list of the parameters accepted by this plugin
+pub const SUPPORTED_PARAMETERS: &[&str] = &[
+    CONFIG_MAP_PARAMETER_NAME,
+    PRIMARY_CONFIG_MAP_PARAMETER_NAME,
+    REPLICA_CONFIG_MAP_PARAMETER_NAME,
+    IMAGE_NAME_PARAMETER_NAME,
+    EXPORTER_VERSION_PARAMETER_NAME,
+    IMAGE_PULL_POLICY_PARAMETER_NAME,
+    IMAGE_PULL_SECRETS_PARAMETER_NAME,
+    EXPORTER_TYPE_PARAMETER_NAME,
+    PORT_PARAMETER_NAME,
+    EXPORTER_LOG_LEVEL_PARAMETER_NAME,
+    EXPORTER_LOG_FORMAT_PARAMETER_NAME,
+    EXPORTERS_PARAMETER_NAME,
+    ENV_PARAMETER_NAME,
+    ENV_FROM_PARAMETER_NAME,
+    ALLOW_ENV_OVERRIDE_PARAMETER_NAME,
+    COMMAND_PARAMETER_NAME,
+    ARGS_PARAMETER_NAME,
+    VOLUMES_PARAMETER_NAME,
+    VOLUME_MOUNTS_PARAMETER_NAME,
+    SCRATCH_DATA_MOUNT_PARAMETER_NAME,
+    RESOURCES_PARAMETER_NAME,
+    SECURITY_CONTEXT_PARAMETER_NAME,
+    ENABLE_STAT_STATEMENTS_PARAMETER_NAME,
+    ENABLE_TRACK_IO_TIMING_PARAMETER_NAME,
+    MONITORING_DATABASES_PARAMETER_NAME,
+    MONITORING_EXTENSIONS_PARAMETER_NAME,
+    CREATE_SERVICE_PARAMETER_NAME,
+    SCRAPE_ANNOTATIONS_PARAMETER_NAME,
+    NETWORK_POLICY_PARAMETER_NAME,
+    MATERIALIZE_DEFAULTS_PARAMETER_NAME,
+];
+
+/// METRICS_BIND_ADDRESS_ENV is the name of the environment variable containing
+/// the address where the plugin metrics are exposed. Metrics are not exposed
+/// when this variable is not set
+pub const METRICS_BIND_ADDRESS_ENV: &str = "METRICS_BIND_ADDRESS";
+
+/// IMAGE_CATALOG_FILE_ENV is the name of the environment variable containing
+/// the path of the file extending the built-in image catalog
+pub const IMAGE_CATALOG_FILE_ENV: &str = "IMAGE_CATALOG_FILE";
+
+/// POLICY_FILE_ENV is the name of the environment variable containing the
+/// path of the file with the organization policy for the plugin parameters
+pub const POLICY_FILE_ENV: &str = "POLICY_FILE";
+
+/// NAMESPACE_DEFAULTS_CONFIG_MAP_NAME is the name of the ConfigMap containing
+/// the default plugin parameters of the clusters in its namespace
+pub const NAMESPACE_DEFAULTS_CONFIG_MAP_NAME: &str = "plugin-generic-exporter-defaults";
+
+/// DISABLED_SERVICES_ENV is the name of the environment variable containing
+/// a comma-separated list of services (e.g. `lifecycle`) that shouldn't be
+/// served and advertised by the plugin
+pub const DISABLED_SERVICES_ENV: &str = "DISABLED_SERVICES";
+
+/// CONFIG_ROLE_LABEL is the label set on the instance Pods reporting the role
+/// the exporter configuration has been selected for
+pub const CONFIG_ROLE_LABEL: &str = "plugin-generic-exporter.leonardoce.io/config-role";
+
+/// DEFAULTS_ANNOTATION is the annotation set on the clusters recording the
+/// source of the parameter values set by the plugin
+pub const DEFAULTS_ANNOTATION: &str = "plugin-generic-exporter.leonardoce.io/defaults";
+
+/// CLUSTER_LABEL is the label set on the resources created by the plugin,
+/// containing the name of the Cluster they belong to
+pub const CLUSTER_LABEL: &str = "plugin-generic-exporter.leonardoce.io/cluster";
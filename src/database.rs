@@ -0,0 +1,244 @@
+use crate::{
+    config::{MonitoringDatabase, MonitoringSettings},
+    helper::DataLoader,
+    resources::{self, ClusterRef, ResourceError},
+};
+use kube::{
+    api::{Api, ApiResource, DeleteParams, DynamicObject, ListParams},
+    core::GroupVersionKind,
+};
+use serde::Serialize;
+
+/// DatabaseStatus is the outcome of the creation of the monitoring
+/// extensions in a database, as reported by CNPG
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseStatus {
+    /// database is the name of the database
+    pub database: String,
+
+    /// applied tells if the database and its extensions have been
+    /// reconciled, and is missing when CNPG didn't reconcile them yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied: Option<bool>,
+
+    /// message describes why the database couldn't be reconciled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// database_api is the API of the CNPG Database resources in a namespace
+fn database_api(client: &kube::Client, namespace: &str) -> Api<DynamicObject> {
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk(
+        "postgresql.cnpg.io",
+        "v1",
+        "Database",
+    ));
+    Api::namespaced_with(client.clone(), namespace, &resource)
+}
+
+/// build_database creates the Database resource ensuring the monitoring
+/// extensions exist in a database. The owner of the database is not set,
+/// as the plugin doesn't manage it, and the database is retained when the
+/// resource is deleted
+fn build_database(
+    loader: &DataLoader,
+    database: &MonitoringDatabase,
+    extensions: &[String],
+) -> serde_json::Value {
+    let cluster = ClusterRef::from_loader(loader);
+
+    let mut metadata = serde_json::json!({
+        "name": database.resource_name(cluster.name),
+        "labels": cluster.labels(),
+    });
+    if let Some(owner_references) = resources::owner_references(loader) {
+        metadata["ownerReferences"] = serde_json::json!(owner_references);
+    }
+
+    serde_json::json!({
+        "apiVersion": "postgresql.cnpg.io/v1",
+        "kind": "Database",
+        "metadata": metadata,
+        "spec": {
+            "cluster": {"name": cluster.name},
+            "name": database.name,
+            "ensure": "present",
+            "databaseReclaimPolicy": "retain",
+            "extensions": extensions
+                .iter()
+                .map(|name| serde_json::json!({"name": name, "ensure": "present"}))
+                .collect::<Vec<_>>(),
+        }
+    })
+}
+
+/// reconcile creates or updates the Database resources for the monitoring
+/// databases of a cluster, removing the ones no longer needed. The databases
+/// already managed by a Database resource not created by the plugin are
+/// skipped, reporting them together with the other errors
+pub async fn reconcile(
+    client: &kube::Client,
+    loader: &DataLoader,
+    settings: &MonitoringSettings,
+) -> Result<(), Vec<ResourceError>> {
+    let cluster = ClusterRef::from_loader(loader);
+    let api = database_api(client, cluster.namespace);
+    let existing = api
+        .list(&ListParams::default())
+        .await
+        .map_err(|err| vec![err.into()])?;
+
+    let mut errors = Vec::new();
+    let mut desired = Vec::new();
+    for database in &settings.databases {
+        if let Some(other) = existing.iter().find(|x| {
+            x.data["spec"]["cluster"]["name"] == cluster.name
+                && x.data["spec"]["name"] == database.name.as_str()
+                && !cluster.is_owner(x.metadata.labels.as_ref())
+        }) {
+            errors.push(ResourceError::DatabaseManaged {
+                database: database.name.clone(),
+                name: other.metadata.name.clone().unwrap_or_default(),
+            });
+            continue;
+        }
+
+        let resource = build_database(loader, database, &settings.extensions);
+        let name = database.resource_name(cluster.name);
+        if let Err(err) = resources::apply(&api, &cluster, "Database", &name, &resource).await {
+            errors.push(err);
+        }
+        desired.push(name);
+    }
+
+    for existing in existing
+        .iter()
+        .filter(|x| cluster.is_owner(x.metadata.labels.as_ref()))
+    {
+        let name = existing.metadata.name.clone().unwrap_or_default();
+        if !desired.contains(&name) {
+            if let Err(err) = api.delete(&name, &DeleteParams::default()).await {
+                errors.push(err.into());
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// remove deletes every Database resource created for a cluster
+pub async fn remove(client: &kube::Client, cluster: &ClusterRef<'_>) -> Result<(), kube::Error> {
+    let api = database_api(client, cluster.namespace);
+    for existing in api.list(&cluster.selector()).await? {
+        api.delete(
+            &existing.metadata.name.unwrap_or_default(),
+            &DeleteParams::default(),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// status reads the outcome of the reconciliation of the Database
+/// resources created for a cluster
+pub async fn status(
+    client: &kube::Client,
+    loader: &DataLoader,
+) -> Result<Vec<DatabaseStatus>, kube::Error> {
+    let cluster = ClusterRef::from_loader(loader);
+    let api = database_api(client, cluster.namespace);
+
+    Ok(api
+        .list(&cluster.selector())
+        .await?
+        .into_iter()
+        .map(|database| database_status(&database.data))
+        .collect())
+}
+
+/// database_status extracts the outcome of the reconciliation from a
+/// Database resource. A failure in creating an extension is reported
+/// when the database itself has been reconciled
+fn database_status(database: &serde_json::Value) -> DatabaseStatus {
+    let status = &database["status"];
+    let failed_extension = status["extensions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|x| x["applied"] == false);
+
+    DatabaseStatus {
+        database: database["spec"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        applied: status["applied"]
+            .as_bool()
+            .map(|applied| applied && failed_extension.is_none()),
+        message: status["message"]
+            .as_str()
+            .filter(|x| !x.is_empty())
+            .or(failed_extension.and_then(|x| x["message"].as_str()))
+            .map(|x| x.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_database() {
+        let database = build_database(
+            &DataLoader::from_parameters(serde_json::json!({})),
+            &MonitoringDatabase {
+                name: "sales_db".to_string(),
+            },
+            &["pg_stat_statements".to_string()],
+        );
+
+        assert_eq!(
+            database["metadata"]["name"],
+            "cluster-example-monitoring-sales-db"
+        );
+        assert_eq!(
+            database["metadata"]["labels"][crate::consts::CLUSTER_LABEL],
+            "cluster-example"
+        );
+        assert_eq!(database["spec"]["name"], "sales_db");
+        assert!(database["spec"].get("owner").is_none());
+        assert_eq!(database["spec"]["cluster"]["name"], "cluster-example");
+        assert_eq!(
+            database["spec"]["extensions"][0]["name"],
+            "pg_stat_statements"
+        );
+    }
+
+    #[test]
+    fn test_database_status() {
+        let status = database_status(&serde_json::json!({
+            "spec": {"name": "app"},
+            "status": {
+                "applied": true,
+                "extensions": [
+                    {"name": "pg_stat_statements", "applied": false, "message": "not available"}
+                ]
+            }
+        }));
+
+        assert_eq!(
+            status,
+            DatabaseStatus {
+                database: "app".to_string(),
+                applied: Some(false),
+                message: Some("not available".to_string()),
+            }
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{consts, helper::DataLoader};
+use k8s_openapi::api::core::v1 as api;
+use kube::api::Api;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Source is where the value of a plugin parameter comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Source {
+    /// Cluster values are set in the Cluster resource
+    Cluster,
+
+    /// Namespace values come from the defaults ConfigMap of the namespace
+    Namespace,
+
+    /// Policy values come from the organization policy
+    Policy,
+
+    /// Default values are the ones used by the plugin when a parameter
+    /// is not specified
+    Default,
+
+    /// Catalog values are resolved from the image catalog
+    Catalog,
+}
+
+/// DefaultedValue is a parameter value set by the plugin
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct DefaultedValue {
+    source: Source,
+    value: String,
+}
+
+/// namespace_defaults reads the default parameters from the defaults
+/// ConfigMap of a namespace. Unknown parameters are ignored
+pub async fn namespace_defaults(
+    client: &kube::Client,
+    namespace: &str,
+) -> Result<HashMap<String, String>, kube::Error> {
+    let api: Api<api::ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let Some(config_map) = api
+        .get_opt(consts::NAMESPACE_DEFAULTS_CONFIG_MAP_NAME)
+        .await?
+    else {
+        return Ok(HashMap::new());
+    };
+
+    let mut result = HashMap::new();
+    for (name, value) in config_map.data.unwrap_or_default() {
+        if consts::SUPPORTED_PARAMETERS.contains(&name.as_str()) {
+            result.insert(name, value);
+        } else {
+            warn!(
+                "Unknown parameter {} in the ConfigMap {}/{}, ignoring it",
+                name,
+                namespace,
+                consts::NAMESPACE_DEFAULTS_CONFIG_MAP_NAME
+            );
+        }
+    }
+    Ok(result)
+}
+
+/// Defaults fills in the parameters of a cluster, keeping track of the
+/// source of the values set by the plugin. The sources are recorded in
+/// an annotation of the cluster, as the values are written in the
+/// cluster parameters
+pub struct Defaults {
+    parameters: HashMap<String, String>,
+    defaulted: BTreeMap<String, DefaultedValue>,
+    invalid: BTreeSet<String>,
+}
+
+impl Defaults {
+    /// new starts from the parameters of a cluster, keeping the sources
+    /// of the values previously set by the plugin which haven't been
+    /// changed since then. The parameters whose value is not valid are
+    /// never set, leaving them to the validation webhook
+    pub fn new(loader: &DataLoader) -> Defaults {
+        let parameters = loader.copy_parameters();
+        let defaulted = recorded(loader)
+            .into_iter()
+            .filter(|(name, defaulted)| parameters.get(name) == Some(&defaulted.value))
+            .collect();
+        Defaults {
+            parameters,
+            defaulted,
+            invalid: loader.invalid_parameters().keys().cloned().collect(),
+        }
+    }
+
+    /// parameters are the current parameter values
+    pub fn parameters(&self) -> &HashMap<String, String> {
+        &self.parameters
+    }
+
+    /// fill sets the parameters which are not specified yet
+    pub fn fill(&mut self, source: Source, values: HashMap<String, String>) {
+        for (name, value) in values {
+            if !self.parameters.contains_key(&name) {
+                self.set(source, &name, value);
+            }
+        }
+    }
+
+    /// set changes the value of a parameter, unless it's already the
+    /// passed one or the cluster specifies an invalid value for it
+    pub fn set(&mut self, source: Source, name: &str, value: String) {
+        if self.parameters.get(name) == Some(&value) || self.invalid.contains(name) {
+            return;
+        }
+        self.defaulted.insert(
+            name.to_string(),
+            DefaultedValue {
+                source,
+                value: value.clone(),
+            },
+        );
+        self.parameters.insert(name.to_string(), value);
+    }
+
+    /// annotations are the cluster annotations recording the sources of
+    /// the values set by the plugin. A stale annotation is left in place,
+    /// as the values it records don't match the parameters anymore
+    pub fn annotations(&self) -> BTreeMap<String, String> {
+        if self.defaulted.is_empty() {
+            return BTreeMap::new();
+        }
+        BTreeMap::from([(
+            consts::DEFAULTS_ANNOTATION.to_string(),
+            serde_json::to_string(&self.defaulted).unwrap_or_default(),
+        )])
+    }
+}
+
+/// recorded are the values previously set by the plugin in a cluster
+fn recorded(loader: &DataLoader) -> BTreeMap<String, DefaultedValue> {
+    loader
+        .cluster_annotation(consts::DEFAULTS_ANNOTATION)
+        .and_then(|x| serde_json::from_str(x).ok())
+        .unwrap_or_default()
+}
+
+/// provenance is the source of every parameter of a cluster. The values
+/// set by the plugin and changed afterwards belong to the cluster
+pub fn provenance(loader: &DataLoader) -> BTreeMap<String, Source> {
+    let recorded = recorded(loader);
+    loader
+        .copy_parameters()
+        .into_iter()
+        .map(|(name, value)| {
+            let source = recorded
+                .get(&name)
+                .filter(|x| x.value == value)
+                .map(|x| x.source)
+                .unwrap_or(Source::Cluster);
+            (name, source)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_precedence() {
+        let loader = DataLoader::from_parameters(serde_json::json!({"configMapName": "mine"}));
+        let mut defaults = Defaults::new(&loader);
+        defaults.fill(
+            Source::Namespace,
+            HashMap::from([
+                ("configMapName".to_string(), "team".to_string()),
+                ("port".to_string(), "9300".to_string()),
+            ]),
+        );
+        defaults.fill(
+            Source::Policy,
+            HashMap::from([("port".to_string(), "9400".to_string())]),
+        );
+
+        assert_eq!(defaults.parameters()["configMapName"], "mine");
+        assert_eq!(defaults.parameters()["port"], "9300");
+        assert_eq!(
+            defaults.annotations()[consts::DEFAULTS_ANNOTATION],
+            r#"{"port":{"source":"namespace","value":"9300"}}"#
+        );
+
+        let patch = loader
+            .calculate_cluster_patch(
+                &loader.updated_cluster(defaults.parameters(), &defaults.annotations()),
+            )
+            .unwrap();
+        assert!(patch
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|x| x["path"] == "/metadata/annotations"));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let cluster = serde_json::json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Cluster",
+            "metadata": {
+                "name": "cluster-example",
+                "namespace": "default",
+                "annotations": {
+                    consts::DEFAULTS_ANNOTATION: r#"{"port":{"source":"namespace","value":"9300"},"imageName":{"source":"catalog","value":"old"}}"#
+                }
+            },
+            "spec": {
+                "plugins": [{
+                    "name": consts::PLUGIN_NAME,
+                    "parameters": {"configMapName": "mine", "port": "9300", "imageName": "new"}
+                }]
+            }
+        });
+        let loader =
+            DataLoader::from_cluster(consts::PLUGIN_NAME, cluster.to_string().as_bytes()).unwrap();
+
+        assert_eq!(
+            provenance(&loader),
+            BTreeMap::from([
+                ("configMapName".to_string(), Source::Cluster),
+                ("imageName".to_string(), Source::Cluster),
+                ("port".to_string(), Source::Namespace),
+            ])
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::helper::DataLoaderError;
+use thiserror::Error;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// CLUSTER_RESOURCE_TYPE is the resource type reported in the
+/// error details when an error refers to a Cluster
+const CLUSTER_RESOURCE_TYPE: &str = "postgresql.cnpg.io/v1/Cluster";
+
+/// Error is the error type of this plugin. Every error is converted
+/// to a gRPC status when it is sent back to CNPG.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    DataLoader(#[from] DataLoaderError),
+
+    #[error("Invalid value for parameter {parameter} of cluster {cluster}: {message}")]
+    InvalidParameter {
+        cluster: String,
+        parameter: String,
+        path_components: Vec<String>,
+        message: String,
+    },
+
+    #[error("Invalid Pod definition for cluster {cluster}: {message}")]
+    InvalidPod { cluster: String, message: String },
+
+    #[error("Error while serializing {context}: {source}")]
+    Serialization {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Error while {context} for cluster {cluster}: {source}")]
+    Kubernetes {
+        cluster: String,
+        context: String,
+        #[source]
+        source: Box<kube::Error>,
+    },
+}
+
+impl Error {
+    /// code is the gRPC status code corresponding to this error
+    pub fn code(&self) -> Code {
+        match self {
+            Error::DataLoader(_) | Error::InvalidParameter { .. } | Error::InvalidPod { .. } => {
+                Code::InvalidArgument
+            }
+            Error::Serialization { .. } => Code::Internal,
+            Error::Kubernetes { .. } => Code::Unavailable,
+        }
+    }
+
+    /// cluster is the name of the cluster this error refers to, if known
+    pub fn cluster(&self) -> Option<&str> {
+        match self {
+            Error::DataLoader(err) => err.cluster(),
+            Error::InvalidParameter { cluster, .. }
+            | Error::InvalidPod { cluster, .. }
+            | Error::Kubernetes { cluster, .. } => Some(cluster),
+            Error::Serialization { .. } => None,
+        }
+    }
+
+    /// field is the path of the cluster definition section that
+    /// caused this error, if known
+    pub fn field(&self) -> Option<String> {
+        match self {
+            Error::DataLoader(err) => err.path_components().map(|path| path.join(".")),
+            Error::InvalidParameter {
+                path_components, ..
+            } => Some(path_components.join(".")),
+            Error::InvalidPod { .. } | Error::Serialization { .. } | Error::Kubernetes { .. } => {
+                None
+            }
+        }
+    }
+}
+
+impl From<Error> for Status {
+    fn from(err: Error) -> Status {
+        let message = err.to_string();
+
+        let mut details = ErrorDetails::new();
+        if let Some(cluster) = err.cluster() {
+            details.set_resource_info(CLUSTER_RESOURCE_TYPE, cluster, "", message.clone());
+        }
+        if let Some(field) = err.field() {
+            details.add_bad_request_violation(field, message.clone());
+        }
+
+        Status::with_error_details(err.code(), message, details)
+    }
+}
+
+impl From<DataLoaderError> for Status {
+    fn from(err: DataLoaderError) -> Status {
+        Error::from(err).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_cluster_definition() {
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let status: Status = DataLoaderError::InvalidClusterDefinition(err).into();
+
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(status.get_details_resource_info().is_none());
+        assert!(status.get_details_bad_request().is_none());
+    }
+
+    #[test]
+    fn test_plugin_not_found() {
+        let status: Status = DataLoaderError::PluginNotFound {
+            cluster: "default/cluster-example".to_string(),
+            name: "test".to_string(),
+        }
+        .into();
+
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status
+                .get_details_resource_info()
+                .expect("cluster is known")
+                .resource_name,
+            "default/cluster-example"
+        );
+        assert_eq!(
+            status
+                .get_details_bad_request()
+                .expect("path is known")
+                .field_violations[0]
+                .field,
+            "spec.plugins"
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameter() {
+        let status: Status = Error::InvalidParameter {
+            cluster: "default/cluster-example".to_string(),
+            parameter: "configMapName".to_string(),
+            path_components: vec![
+                "spec".to_string(),
+                "plugins".to_string(),
+                "0".to_string(),
+                "parameters".to_string(),
+                "configMapName".to_string(),
+            ],
+            message: "this parameter is required".to_string(),
+        }
+        .into();
+
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(status.message().contains("this parameter is required"));
+        assert_eq!(
+            status
+                .get_details_bad_request()
+                .expect("path is known")
+                .field_violations[0]
+                .field,
+            "spec.plugins.0.parameters.configMapName"
+        );
+    }
+
+    #[test]
+    fn test_serialization() {
+        let source = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let status: Status = Error::Serialization {
+            context: "patch".to_string(),
+            source,
+        }
+        .into();
+
+        assert_eq!(status.code(), Code::Internal);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{consts, helper::DataLoader, resources::ClusterRef};
+use k8s_openapi::{
+    api::core::v1 as api,
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time},
+    chrono::Utc,
+};
+use kube::api::{Api, PostParams};
+
+/// warning records a Warning Event on a cluster, to be shown by
+/// `kubectl describe`
+pub async fn warning(
+    client: &kube::Client,
+    loader: &DataLoader,
+    reason: &str,
+    message: &str,
+) -> Result<(), kube::Error> {
+    let event = build_event(loader, "Warning", reason, message);
+    let api: Api<api::Event> =
+        Api::namespaced(client.clone(), ClusterRef::from_loader(loader).namespace);
+    api.create(&PostParams::default(), &event).await?;
+
+    Ok(())
+}
+
+/// build_event creates an Event involving a cluster. The cluster UID is
+/// not known when a cluster is being created
+fn build_event(loader: &DataLoader, type_: &str, reason: &str, message: &str) -> api::Event {
+    let cluster = ClusterRef::from_loader(loader);
+    let now = Time(Utc::now());
+
+    api::Event {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}.", cluster.name)),
+            namespace: Some(cluster.namespace.to_string()),
+            ..Default::default()
+        },
+        involved_object: api::ObjectReference {
+            api_version: Some("postgresql.cnpg.io/v1".to_string()),
+            kind: Some("Cluster".to_string()),
+            name: Some(cluster.name.to_string()),
+            namespace: Some(cluster.namespace.to_string()),
+            uid: loader.cluster_field("/metadata/uid").map(|x| x.to_string()),
+            ..Default::default()
+        },
+        type_: Some(type_.to_string()),
+        reason: Some(reason.to_string()),
+        message: Some(message.to_string()),
+        source: Some(api::EventSource {
+            component: Some(consts::PLUGIN_NAME.to_string()),
+            ..Default::default()
+        }),
+        reporting_component: Some(consts::PLUGIN_NAME.to_string()),
+        first_timestamp: Some(now.clone()),
+        last_timestamp: Some(now),
+        count: Some(1),
+        ..Default::default()
+    }
+}
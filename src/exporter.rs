@@ -0,0 +1,354 @@
+use k8s_openapi::api::core::v1 as api;
+
+/// CONFIG_DIRECTORY is where the exporter configuration is mounted
+pub const CONFIG_DIRECTORY: &str = "/config";
+
+/// PODINFO_DIRECTORY is where the downward API volume exposing the Pod
+/// labels and annotations is mounted
+pub const PODINFO_DIRECTORY: &str = "/etc/podinfo";
+
+/// CONTROLLER_DIRECTORY is where the CNPG scratch-data volume is mounted
+pub const CONTROLLER_DIRECTORY: &str = "/controller";
+
+/// RUN_DIRECTORY is where the CNPG scratch-data volume is mounted to
+/// provide a writable runtime directory
+pub const RUN_DIRECTORY: &str = "/run";
+
+/// POSTGRES_SOCKET_SUB_PATH is the path of the PostgreSQL socket directory
+/// inside the CNPG scratch-data volume
+pub const POSTGRES_SOCKET_SUB_PATH: &str = "run";
+
+/// SCRATCH_DATA_VOLUME_NAME is the name of the CNPG volume containing the
+/// PostgreSQL Unix socket
+pub const SCRATCH_DATA_VOLUME_NAME: &str = "scratch-data";
+
+/// PODINFO_VOLUME_NAME is the name of the downward API volume exposing
+/// the labels and the annotations of the Pod to the exporters
+pub const PODINFO_VOLUME_NAME: &str = "exporter-podinfo";
+
+/// POSTGRES_SOCKET_DIRECTORY is the directory containing the PostgreSQL
+/// Unix socket, inside the CNPG scratch-data volume mounted on /controller
+pub const POSTGRES_SOCKET_DIRECTORY: &str = "/controller/run";
+
+/// ExporterSettings are the values used to run an exporter
+pub struct ExporterSettings {
+    /// config_path is the full path of the exporter configuration file
+    pub config_path: String,
+
+    /// port is the port where the exporter serves its metrics
+    pub port: u16,
+
+    /// log_level is the verbosity of the exporter
+    pub log_level: String,
+
+    /// log_format is the format of the exporter logs, when not using
+    /// the exporter default
+    pub log_format: Option<String>,
+}
+
+/// LOG_LEVELS are the log levels supported by every exporter
+pub const LOG_LEVELS: &[&str] = &["debug", "info", "warn", "error"];
+
+/// ExporterBackend knows the conventions of an exporter implementation:
+/// how it should be configured and where it serves its metrics
+pub trait ExporterBackend: Sync {
+    /// name is the value of the exporterType parameter selecting this backend
+    fn name(&self) -> &'static str;
+
+    /// default_image is the image used when imageName is not specified,
+    /// pinned to an exact version
+    fn default_image(&self) -> &'static str;
+
+    /// default_version is the exporterVersion selecting the default image
+    /// in the built-in image catalog
+    fn default_version(&self) -> &'static str;
+
+    /// config_file_name is the name of the configuration file, which is
+    /// also the key expected in the configuration ConfigMap
+    fn config_file_name(&self) -> &'static str;
+
+    /// default_port is the port where the exporter serves its metrics
+    fn default_port(&self) -> u16;
+
+    /// metrics_path is the HTTP path serving the metrics
+    fn metrics_path(&self) -> &'static str {
+        "/metrics"
+    }
+
+    /// log_formats are the supported log formats. The exporterLogFormat
+    /// parameter can't be used when this list is empty
+    fn log_formats(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// env is the environment of the exporter container
+    fn env(&self, settings: &ExporterSettings) -> Vec<api::EnvVar>;
+
+    /// args are the arguments passed to the exporter container
+    fn args(&self, settings: &ExporterSettings) -> Vec<String>;
+}
+
+/// BACKENDS is the list of the supported exporter implementations. The
+/// first one is used when the exporterType parameter is not specified
+pub const BACKENDS: &[&dyn ExporterBackend] = &[
+    &JustwatchcomSqlExporter,
+    &BurningalchemistSqlExporter,
+    &PostgresExporter,
+];
+
+/// get_backend finds the exporter backend with the passed name, using
+/// the default one when no name has been specified
+pub fn get_backend(name: Option<&str>) -> Option<&'static dyn ExporterBackend> {
+    match name {
+        None => Some(BACKENDS[0]),
+        Some(name) => BACKENDS.iter().find(|x| x.name() == name).copied(),
+    }
+}
+
+/// backend_names is the comma-separated list of supported exporter types
+pub fn backend_names() -> String {
+    BACKENDS
+        .iter()
+        .map(|x| x.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// pod_info_env is the environment exposing the Pod and its instance to the
+/// exporter via the downward API. config_role is the role the exporter
+/// configuration has been selected for
+pub fn pod_info_env(config_role: &str) -> Vec<api::EnvVar> {
+    vec![
+        field_env_var("POD_NAME", "metadata.name"),
+        field_env_var("POD_NAMESPACE", "metadata.namespace"),
+        field_env_var("CNPG_CLUSTER_NAME", "metadata.labels['cnpg.io/cluster']"),
+        field_env_var(
+            "CNPG_INSTANCE_ROLE",
+            "metadata.labels['cnpg.io/instanceRole']",
+        ),
+        env_var("EXPORTER_CONFIG_ROLE", config_role),
+    ]
+}
+
+/// field_env_var creates an environment variable set from a field
+/// of the Pod via the downward API
+fn field_env_var(name: &str, field_path: &str) -> api::EnvVar {
+    api::EnvVar {
+        name: name.to_string(),
+        value: None,
+        value_from: Some(api::EnvVarSource {
+            field_ref: Some(api::ObjectFieldSelector {
+                api_version: None,
+                field_path: field_path.to_string(),
+            }),
+            ..Default::default()
+        }),
+    }
+}
+
+/// PROMETHEUS_LOG_FORMATS are the log formats supported by the exporters
+/// based on the Prometheus exporter toolkit
+const PROMETHEUS_LOG_FORMATS: &[&str] = &["logfmt", "json"];
+
+/// prometheus_args are the arguments of the exporters based on the
+/// Prometheus exporter toolkit
+fn prometheus_args(settings: &ExporterSettings) -> Vec<String> {
+    let mut result = vec![
+        format!("--config.file={}", settings.config_path),
+        format!("--web.listen-address=:{}", settings.port),
+        format!("--log.level={}", settings.log_level),
+    ];
+    if let Some(log_format) = &settings.log_format {
+        result.push(format!("--log.format={}", log_format));
+    }
+    result
+}
+
+fn env_var(name: &str, value: &str) -> api::EnvVar {
+    api::EnvVar {
+        name: name.to_string(),
+        value: Some(value.to_string()),
+        value_from: None,
+    }
+}
+
+/// JustwatchcomSqlExporter is https://github.com/justwatchcom/sql_exporter
+pub struct JustwatchcomSqlExporter;
+
+impl ExporterBackend for JustwatchcomSqlExporter {
+    fn name(&self) -> &'static str {
+        "justwatchcom-sql-exporter"
+    }
+
+    fn default_image(&self) -> &'static str {
+        crate::consts::IMAGE_NAME_PARAMETER_DEFAULT
+    }
+
+    fn default_version(&self) -> &'static str {
+        "0.5"
+    }
+
+    fn config_file_name(&self) -> &'static str {
+        "config.yml"
+    }
+
+    fn default_port(&self) -> u16 {
+        9237
+    }
+
+    fn env(&self, settings: &ExporterSettings) -> Vec<api::EnvVar> {
+        vec![
+            env_var("CONFIG", &settings.config_path),
+            env_var("LOGLEVEL", &settings.log_level),
+        ]
+    }
+
+    fn args(&self, settings: &ExporterSettings) -> Vec<String> {
+        // We don't pass any argument by default, to be compatible with
+        // images not using the exporter as entrypoint
+        if settings.port == self.default_port() {
+            vec![]
+        } else {
+            vec![format!("-web.listen-address=:{}", settings.port)]
+        }
+    }
+}
+
+/// BurningalchemistSqlExporter is https://github.com/burningalchemist/sql_exporter
+pub struct BurningalchemistSqlExporter;
+
+impl ExporterBackend for BurningalchemistSqlExporter {
+    fn name(&self) -> &'static str {
+        "burningalchemist-sql-exporter"
+    }
+
+    fn default_image(&self) -> &'static str {
+        "docker.io/burningalchemist/sql_exporter:0.14.3"
+    }
+
+    fn default_version(&self) -> &'static str {
+        "0.14.3"
+    }
+
+    fn config_file_name(&self) -> &'static str {
+        "sql_exporter.yml"
+    }
+
+    fn default_port(&self) -> u16 {
+        9399
+    }
+
+    fn log_formats(&self) -> &'static [&'static str] {
+        PROMETHEUS_LOG_FORMATS
+    }
+
+    fn env(&self, _settings: &ExporterSettings) -> Vec<api::EnvVar> {
+        vec![]
+    }
+
+    fn args(&self, settings: &ExporterSettings) -> Vec<String> {
+        prometheus_args(settings)
+    }
+}
+
+/// PostgresExporter is https://github.com/prometheus-community/postgres_exporter
+pub struct PostgresExporter;
+
+impl ExporterBackend for PostgresExporter {
+    fn name(&self) -> &'static str {
+        "postgres-exporter"
+    }
+
+    fn default_image(&self) -> &'static str {
+        "quay.io/prometheuscommunity/postgres-exporter:v0.15.0"
+    }
+
+    fn default_version(&self) -> &'static str {
+        "0.15.0"
+    }
+
+    fn config_file_name(&self) -> &'static str {
+        "postgres_exporter.yml"
+    }
+
+    fn default_port(&self) -> u16 {
+        // The upstream default is 9187, which is already used by
+        // the CNPG instance manager
+        9188
+    }
+
+    fn log_formats(&self) -> &'static [&'static str] {
+        PROMETHEUS_LOG_FORMATS
+    }
+
+    fn env(&self, _settings: &ExporterSettings) -> Vec<api::EnvVar> {
+        vec![env_var(
+            "DATA_SOURCE_NAME",
+            &format!(
+                "host={} user=postgres dbname=postgres sslmode=disable",
+                POSTGRES_SOCKET_DIRECTORY
+            ),
+        )]
+    }
+
+    fn args(&self, settings: &ExporterSettings) -> Vec<String> {
+        prometheus_args(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(backend: &dyn ExporterBackend) -> ExporterSettings {
+        ExporterSettings {
+            config_path: format!("{}/{}", CONFIG_DIRECTORY, backend.config_file_name()),
+            port: backend.default_port(),
+            log_level: "info".to_string(),
+            log_format: None,
+        }
+    }
+
+    #[test]
+    fn test_default_backend() {
+        let backend = get_backend(None).unwrap();
+        assert_eq!(backend.name(), "justwatchcom-sql-exporter");
+
+        let env = backend.env(&settings(backend));
+        assert_eq!(env[0].name, "CONFIG");
+        assert_eq!(env[0].value.as_deref(), Some("/config/config.yml"));
+    }
+
+    #[test]
+    fn test_unknown_backend() {
+        assert!(get_backend(Some("unknown")).is_none());
+    }
+
+    #[test]
+    fn test_backends_avoid_instance_manager_port() {
+        for backend in BACKENDS {
+            assert_ne!(backend.default_port(), 9187, "{}", backend.name());
+        }
+    }
+
+    #[test]
+    fn test_backend_args() {
+        let backend = get_backend(Some("burningalchemist-sql-exporter")).unwrap();
+        let args = backend.args(&settings(backend));
+
+        assert!(args.contains(&"--config.file=/config/sql_exporter.yml".to_string()));
+        assert!(args.contains(&"--web.listen-address=:9399".to_string()));
+    }
+
+    #[test]
+    fn test_backend_log_format() {
+        let backend = get_backend(Some("postgres-exporter")).unwrap();
+        let mut settings = settings(backend);
+        settings.log_format = Some("json".to_string());
+
+        assert!(backend
+            .args(&settings)
+            .contains(&"--log.format=json".to_string()));
+        assert!(get_backend(None).unwrap().log_formats().is_empty());
+    }
+}
@@ -1,94 +1,219 @@
 use crate::cnpg;
-use anyhow::Result;
-use json_patch;
-use serde_json;
-use std::collections::HashMap;
+use crate::error::Error;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum DataLoaderError {
-    #[error("Plugin with specified name was not found")]
-    PluginNotFound { name: String },
+    #[error("Cannot decode the cluster definition: {0}")]
+    InvalidClusterDefinition(#[from] serde_json::Error),
 
-    #[error("Unexpected value in CNPG .spec.plugin section")]
-    UnexpectedPluginSection,
+    #[error("Plugin {name} was not found in cluster {cluster}")]
+    PluginNotFound { cluster: String, name: String },
 
-    #[error("Unexpected value in .spec.plugin.[x].parameters")]
-    UnexpectedPluginParameters,
+    #[error("Unexpected value in .spec.plugins section of cluster {cluster}")]
+    UnexpectedPluginSection { cluster: String },
+
+    #[error("Unexpected value in .spec.plugins[{index}].parameters of cluster {cluster}")]
+    UnexpectedPluginParameters { cluster: String, index: usize },
+}
+
+impl DataLoaderError {
+    /// cluster is the name of the cluster whose definition caused
+    /// this error, if it is known
+    pub fn cluster(&self) -> Option<&str> {
+        match self {
+            DataLoaderError::InvalidClusterDefinition(_) => None,
+            DataLoaderError::PluginNotFound { cluster, .. }
+            | DataLoaderError::UnexpectedPluginSection { cluster }
+            | DataLoaderError::UnexpectedPluginParameters { cluster, .. } => Some(cluster),
+        }
+    }
+
+    /// path_components is the path of the cluster definition
+    /// section that caused this error, if it is known
+    pub fn path_components(&self) -> Option<Vec<String>> {
+        match self {
+            DataLoaderError::InvalidClusterDefinition(_) => None,
+            DataLoaderError::PluginNotFound { .. }
+            | DataLoaderError::UnexpectedPluginSection { .. } => {
+                Some(vec!["spec".to_string(), "plugins".to_string()])
+            }
+            DataLoaderError::UnexpectedPluginParameters { index, .. } => Some(vec![
+                "spec".to_string(),
+                "plugins".to_string(),
+                format!("{}", index),
+                "parameters".to_string(),
+            ]),
+        }
+    }
 }
 
 pub struct DataLoader {
     cluster: serde_json::Value,
+    cluster_name: String,
     parameters: HashMap<String, String>,
+    invalid_parameters: BTreeMap<String, String>,
     plug_index: usize,
 }
 
 impl DataLoader {
     /// from_cluster create a new helper given a Cluster definition as
     /// passed by CNPG
-    pub fn from_cluster(name: &str, definition: &[u8]) -> Result<DataLoader> {
+    pub fn from_cluster(name: &str, definition: &[u8]) -> Result<DataLoader, DataLoaderError> {
         let cluster: serde_json::Value = serde_json::from_slice(definition)?;
+        let cluster_name = format!(
+            "{}/{}",
+            cluster["metadata"]["namespace"]
+                .as_str()
+                .unwrap_or_default(),
+            cluster["metadata"]["name"].as_str().unwrap_or_default()
+        );
 
-        let plugins = cluster["spec"]["plugins"]
-            .as_array()
-            .ok_or(DataLoaderError::UnexpectedPluginSection)?;
+        let plugins = cluster["spec"]["plugins"].as_array().ok_or_else(|| {
+            DataLoaderError::UnexpectedPluginSection {
+                cluster: cluster_name.clone(),
+            }
+        })?;
 
         let (idx, current_plugin) = plugins
             .iter()
             .enumerate()
-            .filter(|(_, x)| x["name"] == name)
-            .next()
-            .ok_or(DataLoaderError::PluginNotFound {
+            .find(|(_, x)| x["name"] == name)
+            .ok_or_else(|| DataLoaderError::PluginNotFound {
+                cluster: cluster_name.clone(),
                 name: name.to_string(),
             })?;
 
-        let parameters = if current_plugin["parameters"].is_null() {
-            HashMap::new()
-        } else {
-            current_plugin["parameters"]
-                .as_object()
-                .ok_or(DataLoaderError::UnexpectedPluginParameters)?
-                .iter()
-                .map(|(name, value)| {
-                    (
-                        name.to_string(),
-                        value
-                            .as_str()
-                            .map(|x| x.to_string())
-                            .unwrap_or("".to_string()),
-                    )
-                })
-                .collect()
-        };
+        let mut parameters = HashMap::new();
+        let mut invalid_parameters = BTreeMap::new();
+        if !current_plugin["parameters"].is_null() {
+            let values = current_plugin["parameters"].as_object().ok_or_else(|| {
+                DataLoaderError::UnexpectedPluginParameters {
+                    cluster: cluster_name.clone(),
+                    index: idx,
+                }
+            })?;
+            for (name, value) in values {
+                match coerce_parameter_value(value) {
+                    Ok(value) => parameters.insert(name.to_string(), value),
+                    Err(message) => invalid_parameters.insert(name.to_string(), message),
+                };
+            }
+        }
 
         Ok(DataLoader {
             cluster,
+            cluster_name,
             parameters,
+            invalid_parameters,
             plug_index: idx,
         })
     }
 
+    /// cluster_name is the name of the cluster, prefixed with its namespace
+    pub fn cluster_name(&self) -> &str {
+        &self.cluster_name
+    }
+
+    /// cluster_field is the value of a string field of the cluster
+    /// definition, identified by a JSON pointer
+    pub fn cluster_field(&self, pointer: &str) -> Option<&str> {
+        self.cluster.pointer(pointer).and_then(|x| x.as_str())
+    }
+
+    /// cluster_annotation is the value of an annotation of the cluster
+    pub fn cluster_annotation(&self, name: &str) -> Option<&str> {
+        self.cluster["metadata"]["annotations"][name].as_str()
+    }
+
+    /// target_primary is the name of the instance which is, or is being
+    /// promoted to be, the primary of the cluster. Before the cluster
+    /// is bootstrapped, this is the first instance
+    pub fn target_primary(&self) -> String {
+        let status = &self.cluster["status"];
+        status["targetPrimary"]
+            .as_str()
+            .or(status["currentPrimary"].as_str())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| {
+                format!(
+                    "{}-1",
+                    self.cluster["metadata"]["name"]
+                        .as_str()
+                        .unwrap_or_default()
+                )
+            })
+    }
+
+    /// invalid_parameters are the plugin parameters whose value can't be
+    /// interpreted, with the reason
+    pub fn invalid_parameters(&self) -> &BTreeMap<String, String> {
+        &self.invalid_parameters
+    }
+
     /// get_parameters find the value of a configuration parameter
     pub fn get_parameter(&self, name: &str) -> Option<String> {
         self.parameters.get(name).map(|x| x.to_string())
     }
 
+    /// get_structured_parameter decodes the value of a configuration parameter
+    /// containing a JSON or YAML document. The error describes why the value
+    /// couldn't be decoded
+    pub fn get_structured_parameter<T: DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> Result<Option<T>, String> {
+        self.parameters
+            .get(name)
+            .map(|value| parse_structured_value(value))
+            .transpose()
+    }
+
+    /// get_bool_parameter decodes the value of a configuration parameter
+    /// containing a boolean
+    pub fn get_bool_parameter(&self, name: &str) -> Result<Option<bool>, String> {
+        self.parameters
+            .get(name)
+            .map(|value| parse_bool(value))
+            .transpose()
+    }
+
+    /// parameter_path_components is the path of a parameter inside
+    /// the cluster definition
+    pub fn parameter_path_components(&self, name: &str) -> Vec<String> {
+        vec![
+            "spec".to_string(),
+            "plugins".to_string(),
+            format!("{}", self.plug_index),
+            "parameters".to_string(),
+            name.to_string(),
+        ]
+    }
+
     // create_validation_error creates a validatoin error for the parameter
     // with a certain name
     pub fn create_validation_error(&self, name: &str, message: &str) -> cnpg::ValidationError {
         cnpg::ValidationError {
-            path_components: vec![
-                "spec".to_string(),
-                "plugins".to_string(),
-                format!("{}", self.plug_index),
-                "parameters".to_string(),
-                name.to_string(),
-            ],
+            path_components: self.parameter_path_components(name),
             value: self.get_parameter(name).unwrap_or_default(),
             message: message.to_string(),
         }
     }
 
+    /// create_parameter_error creates an error for the parameter
+    /// with a certain name, to be returned to CNPG
+    pub fn create_parameter_error(&self, name: &str, message: &str) -> Error {
+        Error::InvalidParameter {
+            cluster: self.cluster_name.clone(),
+            parameter: name.to_string(),
+            path_components: self.parameter_path_components(name),
+            message: message.to_string(),
+        }
+    }
+
     /// copy_parameters returns a copy of the Plugin parameters.
     /// This is typically used to set plugin default values and them
     /// computing the JSON difference to be returned from CNPG
@@ -96,21 +221,124 @@ impl DataLoader {
         self.parameters.clone()
     }
 
-    /// calculate_cluster_patch calculates the JSON patch difference between
-    /// the cluster and a new cluster definition where the passed parameters
-    /// are used
-    pub fn calculate_cluster_patch(
+    /// with_parameters creates a helper for the same cluster, using the
+    /// passed plugin parameters
+    #[cfg(feature = "operator")]
+    pub fn with_parameters(&self, parameters: HashMap<String, String>) -> DataLoader {
+        DataLoader {
+            cluster: self.cluster.clone(),
+            cluster_name: self.cluster_name.clone(),
+            parameters,
+            invalid_parameters: self.invalid_parameters.clone(),
+            plug_index: self.plug_index,
+        }
+    }
+
+    /// updated_cluster is a new cluster definition where the passed
+    /// parameters and annotations are used
+    #[cfg(feature = "operator")]
+    pub fn updated_cluster(
         &self,
         new_parameters: &HashMap<String, String>,
-    ) -> Result<serde_json::Value> {
+        annotations: &BTreeMap<String, String>,
+    ) -> serde_json::Value {
         let mut new_cluster = self.cluster.clone();
-        let new_parameters_json: serde_json::Value = serde_json::to_value(new_parameters)?;
 
-        new_cluster["spec"]["plugins"][self.plug_index]["parameters"] = new_parameters_json;
-        Ok(serde_json::to_value(json_patch::diff(
-            &self.cluster,
-            &new_cluster,
-        ))?)
+        for (name, value) in annotations {
+            let current = &mut new_cluster["metadata"]["annotations"];
+            if !current.is_object() {
+                *current = serde_json::Value::Object(Default::default());
+            }
+            current[name] = serde_json::Value::String(value.clone());
+        }
+
+        // We only touch the parameters whose value has been changed, to
+        // avoid rewriting the ones that have been coerced to a string. The
+        // invalid values are kept, to be reported by the validation webhook
+        if new_parameters != &self.parameters {
+            let parameters = &mut new_cluster["spec"]["plugins"][self.plug_index]["parameters"];
+            if !parameters.is_object() {
+                *parameters = serde_json::Value::Object(Default::default());
+            }
+            let parameters = parameters
+                .as_object_mut()
+                .expect("parameters have just been set to an object");
+
+            parameters.retain(|name, _| {
+                new_parameters.contains_key(name) || self.invalid_parameters.contains_key(name)
+            });
+            for (name, value) in new_parameters {
+                if self.parameters.get(name) != Some(value) {
+                    parameters.insert(name.clone(), serde_json::Value::String(value.clone()));
+                }
+            }
+        }
+
+        new_cluster
+    }
+
+    /// calculate_cluster_patch calculates the JSON patch difference between
+    /// the cluster and a new cluster definition
+    #[cfg(feature = "operator")]
+    pub fn calculate_cluster_patch(
+        &self,
+        new_cluster: &serde_json::Value,
+    ) -> Result<serde_json::Value, Error> {
+        serde_json::to_value(json_patch::diff(&self.cluster, new_cluster)).map_err(|source| {
+            Error::Serialization {
+                context: "cluster patch".to_string(),
+                source,
+            }
+        })
+    }
+}
+
+/// coerce_parameter_value converts a plugin parameter value to the string
+/// representation used by the plugin:
+///
+/// - strings are used as they are;
+/// - numbers and booleans are converted to their JSON representation;
+/// - objects and arrays are encoded as JSON documents;
+/// - null values are not valid.
+pub fn coerce_parameter_value(value: &serde_json::Value) -> Result<String, String> {
+    match value {
+        serde_json::Value::Null => Err("null is not a valid value".to_string()),
+        serde_json::Value::String(value) => Ok(value.clone()),
+        serde_json::Value::Bool(_) | serde_json::Value::Number(_) => Ok(value.to_string()),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => Ok(value.to_string()),
+    }
+}
+
+/// parse_structured_value decodes a parameter value containing a JSON or YAML
+/// document. JSON is valid YAML, so a YAML parser is used for both
+pub fn parse_structured_value<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_yaml::from_str(value).map_err(|err| format!("expected a JSON or YAML document: {}", err))
+}
+
+/// parse_bool decodes a parameter value containing a boolean
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err("expected true or false".to_string()),
+    }
+}
+
+#[cfg(test)]
+impl DataLoader {
+    /// from_parameters creates a helper for a test cluster
+    /// using the passed plugin parameters
+    pub fn from_parameters(parameters: serde_json::Value) -> DataLoader {
+        let cluster = serde_json::json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Cluster",
+            "metadata": {"name": "cluster-example", "namespace": "default"},
+            "spec": {
+                "plugins": [{"name": crate::consts::PLUGIN_NAME, "parameters": parameters}]
+            }
+        });
+        DataLoader::from_cluster(crate::consts::PLUGIN_NAME, cluster.to_string().as_bytes())
+            .unwrap()
     }
 }
 
@@ -210,6 +438,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "operator")]
     fn test_cluster_patch() {
         let helper =
             DataLoader::from_cluster(crate::consts::PLUGIN_NAME, CLUSTER_JSON.as_bytes()).unwrap();
@@ -223,11 +452,141 @@ mod tests {
             .or_insert("Always".to_string());
 
         let patch = helper
-            .calculate_cluster_patch(&new_params)
+            .calculate_cluster_patch(&helper.updated_cluster(&new_params, &BTreeMap::new()))
             .expect("error while calculating patch");
         assert_eq!(patch.as_array().expect("JSON patches are arrays").len(), 2);
     }
 
+    #[test]
+    fn test_decode_missing_plugin() {
+        let err = DataLoader::from_cluster("unknown.plugin.io", CLUSTER_JSON.as_bytes())
+            .err()
+            .expect("the plugin is not there");
+
+        assert!(matches!(err, DataLoaderError::PluginNotFound { .. }));
+        assert_eq!(err.cluster(), Some("default/cluster-example"));
+    }
+
+    #[test]
+    fn test_parameter_error() {
+        let helper =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, CLUSTER_JSON.as_bytes()).unwrap();
+
+        match helper.create_parameter_error("configMapName", "wrong") {
+            Error::InvalidParameter {
+                cluster,
+                path_components,
+                ..
+            } => {
+                assert_eq!(cluster, "default/cluster-example");
+                assert_eq!(
+                    path_components,
+                    vec!["spec", "plugins", "0", "parameters", "configMapName"]
+                );
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_decode_structured_parameters() {
+        let helper = DataLoader::from_parameters(serde_json::json!({
+            "port": 9400,
+            "enabled": true,
+            "ratio": 1.5,
+            "missing": null,
+            "list": ["one", "two"],
+            "object": {"key": "value"}
+        }));
+
+        assert_eq!(helper.get_parameter("port").unwrap(), "9400");
+        assert_eq!(helper.get_parameter("enabled").unwrap(), "true");
+        assert_eq!(helper.get_parameter("ratio").unwrap(), "1.5");
+        assert!(helper.get_parameter("missing").is_none());
+        assert_eq!(
+            helper.invalid_parameters()["missing"],
+            "null is not a valid value"
+        );
+        assert_eq!(
+            helper
+                .get_structured_parameter::<Vec<String>>("list")
+                .unwrap()
+                .unwrap(),
+            vec!["one", "two"]
+        );
+        assert_eq!(
+            helper
+                .get_structured_parameter::<HashMap<String, String>>("object")
+                .unwrap()
+                .unwrap()["key"],
+            "value"
+        );
+    }
+
+    #[test]
+    fn test_structured_parameters_from_strings() {
+        let helper = DataLoader::from_parameters(serde_json::json!({
+            "json": r#"["one", "two"]"#,
+            "yaml": "- one\n- two\n",
+            "wrong": "key: [unterminated"
+        }));
+
+        for name in ["json", "yaml"] {
+            assert_eq!(
+                helper
+                    .get_structured_parameter::<Vec<String>>(name)
+                    .unwrap()
+                    .unwrap(),
+                vec!["one", "two"]
+            );
+        }
+        assert!(helper
+            .get_structured_parameter::<Vec<String>>("wrong")
+            .is_err());
+        assert!(helper
+            .get_structured_parameter::<Vec<String>>("unknown")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "operator")]
+    fn test_cluster_patch_keeps_coerced_values() {
+        let helper = DataLoader::from_parameters(serde_json::json!({"port": 9400}));
+
+        let mut new_params = helper.copy_parameters();
+        new_params.insert("imageName".to_string(), "thisImage".to_string());
+
+        let patch = helper
+            .calculate_cluster_patch(&helper.updated_cluster(&new_params, &BTreeMap::new()))
+            .unwrap();
+        assert_eq!(
+            patch,
+            serde_json::json!([{
+                "op": "add",
+                "path": "/spec/plugins/0/parameters/imageName",
+                "value": "thisImage"
+            }])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "operator")]
+    fn test_cluster_patch_unchanged() {
+        let helper = DataLoader::from_cluster(
+            crate::consts::PLUGIN_NAME,
+            CLUSTER_JSON_NULL_PARAMETERS.as_bytes(),
+        )
+        .unwrap();
+
+        let patch = helper
+            .calculate_cluster_patch(
+                &helper.updated_cluster(&helper.copy_parameters(), &BTreeMap::new()),
+            )
+            .unwrap();
+        assert_eq!(patch, serde_json::json!([]));
+    }
+
     #[test]
     fn test_decode_null_parameters() {
         let helper = DataLoader::from_cluster(
@@ -249,4 +608,20 @@ mod tests {
 
         assert_eq!(helper.parameters.len(), 0);
     }
+
+    #[test]
+    fn test_target_primary() {
+        let helper = DataLoader::from_parameters(serde_json::json!({}));
+        assert_eq!(helper.target_primary(), "cluster-example-1");
+
+        let cluster = serde_json::json!({
+            "metadata": {"name": "cluster-example", "namespace": "default"},
+            "spec": {"plugins": [{"name": crate::consts::PLUGIN_NAME}]},
+            "status": {"currentPrimary": "cluster-example-1", "targetPrimary": "cluster-example-2"}
+        });
+        let helper =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, cluster.to_string().as_bytes())
+                .unwrap();
+        assert_eq!(helper.target_primary(), "cluster-example-2");
+    }
 }
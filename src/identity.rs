@@ -1,9 +1,18 @@
-use crate::cnpg;
+use crate::{capabilities::Registry, cnpg, state::PluginState};
 use std::collections::HashMap;
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
 
-#[derive(Debug, Default)]
-pub struct IdentityImpl {}
+pub struct IdentityImpl {
+    state: Arc<PluginState>,
+    registry: Arc<Registry>,
+}
+
+impl IdentityImpl {
+    pub fn new(state: Arc<PluginState>, registry: Arc<Registry>) -> Self {
+        IdentityImpl { state, registry }
+    }
+}
 
 #[tonic::async_trait]
 impl cnpg::identity_server::Identity for IdentityImpl {
@@ -14,7 +23,7 @@ impl cnpg::identity_server::Identity for IdentityImpl {
     ) -> Result<Response<cnpg::GetPluginMetadataResponse>, Status> {
         Ok(Response::new(cnpg::GetPluginMetadataResponse {
             name: crate::consts::PLUGIN_NAME.to_string(),
-            version: "0.0.1".to_string(),
+            version: crate::consts::VERSION.to_string(),
             display_name: "Generic SQL Exporter plugin".to_string(),
             description: "Add the generic SQL exporter sidecar to CNPG instances".to_string(),
             project_url: "https://github.com/leonardoce/plugin-generic-exporter".to_string(),
@@ -24,7 +33,7 @@ impl cnpg::identity_server::Identity for IdentityImpl {
                 .to_string(),
             maturity: "alpha".to_string(),
             vendor: "Leonardo Cecchi".to_string(),
-            manifest: HashMap::new(),
+            manifest: manifest(),
         }))
     }
 
@@ -34,22 +43,7 @@ impl cnpg::identity_server::Identity for IdentityImpl {
         _request: Request<cnpg::GetPluginCapabilitiesRequest>,
     ) -> Result<Response<cnpg::GetPluginCapabilitiesResponse>, Status> {
         Ok(Response::new(cnpg::GetPluginCapabilitiesResponse {
-            capabilities: vec![
-                cnpg::PluginCapability {
-                    r#type: Some(cnpg::plugin_capability::Type::Service(
-                        cnpg::plugin_capability::Service {
-                            r#type: cnpg::plugin_capability::service::Type::LifecycleService.into(),
-                        },
-                    )),
-                },
-                cnpg::PluginCapability {
-                    r#type: Some(cnpg::plugin_capability::Type::Service(
-                        cnpg::plugin_capability::Service {
-                            r#type: cnpg::plugin_capability::service::Type::OperatorService.into(),
-                        },
-                    )),
-                },
-            ],
+            capabilities: self.registry.plugin_capabilities(),
         }))
     }
 
@@ -58,6 +52,67 @@ impl cnpg::identity_server::Identity for IdentityImpl {
         &self,
         _request: Request<cnpg::ProbeRequest>,
     ) -> Result<Response<cnpg::ProbeResponse>, Status> {
-        Ok(Response::new(cnpg::ProbeResponse { ready: true }))
+        Ok(Response::new(cnpg::ProbeResponse {
+            ready: self.state.check_readiness().await.is_ok(),
+        }))
+    }
+}
+
+/// manifest builds the plugin manifest, containing the information that
+/// tooling can use to discover how this plugin can be used
+fn manifest() -> HashMap<String, String> {
+    HashMap::from([
+        (
+            "defaultImage".to_string(),
+            crate::catalog::get()
+                .default_image(crate::exporter::BACKENDS[0].name())
+                .unwrap_or(crate::consts::IMAGE_NAME_PARAMETER_DEFAULT)
+                .to_string(),
+        ),
+        (
+            "supportedParameters".to_string(),
+            crate::consts::SUPPORTED_PARAMETERS.join(","),
+        ),
+        (
+            "minimumCNPGVersion".to_string(),
+            crate::consts::MINIMUM_CNPG_VERSION.to_string(),
+        ),
+        (
+            "supportedSidecarModes".to_string(),
+            crate::consts::SIDECAR_MODES.join(","),
+        ),
+        (
+            "gitCommit".to_string(),
+            crate::consts::GIT_COMMIT.to_string(),
+        ),
+        (
+            "buildDate".to_string(),
+            crate::consts::BUILD_DATE.to_string(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_from_crate() {
+        assert!(crate::consts::VERSION.starts_with(&format!("{}+", env!("CARGO_PKG_VERSION"))));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = manifest();
+
+        assert_eq!(
+            manifest["defaultImage"],
+            crate::consts::IMAGE_NAME_PARAMETER_DEFAULT
+        );
+        assert!(manifest["supportedParameters"]
+            .split(',')
+            .any(|x| x == crate::consts::CONFIG_MAP_PARAMETER_NAME));
+        assert!(manifest.contains_key("minimumCNPGVersion"));
+        assert!(manifest.contains_key("supportedSidecarModes"));
     }
 }
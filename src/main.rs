@@ -1,16 +1,49 @@
-use log::info;
-use simplelog;
+// The code shared by the services is only partially used when some of
+// them are not compiled in
+#![cfg_attr(
+    not(all(feature = "lifecycle", feature = "operator", feature = "reconciler")),
+    allow(unused)
+)]
+
+use log::{error, info, warn};
 use std::path::Path;
+use std::sync::Arc;
 use tokio::net::UnixListener;
 use tokio_stream::wrappers::UnixListenerStream;
-use tonic::transport::Server;
+use tonic::{server::NamedService, transport::Server};
 
+mod capabilities;
+mod catalog;
 mod cnpg;
+mod config;
 mod consts;
+#[cfg(any(feature = "operator", feature = "reconciler"))]
+mod database;
+#[cfg(feature = "operator")]
+mod defaults;
+mod error;
+#[cfg(feature = "operator")]
+mod events;
+mod exporter;
 mod helper;
 mod identity;
+mod metrics;
+#[cfg(any(feature = "operator", feature = "reconciler"))]
+mod network_policy;
+#[cfg(feature = "operator")]
 mod operator;
+#[cfg(feature = "lifecycle")]
 mod operator_lifecycle;
+mod policy;
+#[cfg(feature = "operator")]
+mod postgresql_settings;
+#[cfg(feature = "reconciler")]
+mod reconciler;
+#[cfg(any(feature = "operator", feature = "reconciler"))]
+mod resources;
+#[cfg(any(feature = "operator", feature = "reconciler"))]
+mod service;
+mod state;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,27 +55,115 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     std::fs::create_dir_all(Path::new(path).parent().unwrap())?;
 
-    let uds = UnixListener::bind(path)?;
-    let uds_stream = UnixListenerStream::new(uds);
+    // The Kubernetes API is not required for the basic plugin features,
+    // we just use it when it's available
+    let kubernetes_client = match kube::Client::try_default().await {
+        Ok(client) => Some(client),
+        Err(err) => {
+            warn!("Kubernetes API not available: {}", err);
+            None
+        }
+    };
+
+    if let Ok(address) = std::env::var(consts::METRICS_BIND_ADDRESS_ENV) {
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(address).await {
+                error!("Error while serving metrics: {}", err);
+            }
+        });
+    }
+
+    // The plugin is not ready until its configuration is valid
+    let state = Arc::new(state::PluginState::new(kubernetes_client));
+    let configuration = [
+        ("image catalog", catalog::load()),
+        ("policy", policy::POLICY.load()),
+    ];
+    for (_, result) in &configuration {
+        if let Err(err) = result {
+            error!("{}", err);
+        }
+    }
+    state.mark_configuration_loaded(&configuration);
+    tokio::spawn(policy::POLICY.watch(state.clone()));
+
+    // Every service module compiled in registers itself, and the services
+    // we serve are the ones we advertise
+    let mut registry = capabilities::Registry::from_env();
+    #[cfg(feature = "lifecycle")]
+    operator_lifecycle::register(&mut registry);
+    #[cfg(feature = "operator")]
+    operator::register(&mut registry);
+    #[cfg(feature = "reconciler")]
+    reconciler::register(&mut registry);
+    let registry = Arc::new(registry);
+
+    // The standard gRPC health service reports the serving status of
+    // every CNPG-i service we expose, following the readiness of the
+    // plugin, so that generic tools like grpc_health_probe can be used
+    // against the plugin socket
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
 
-    let identity_implementation = identity::IdentityImpl::default();
-    let operator_lifecycle_implementation = operator_lifecycle::OperatorLifecycleImpl::default();
-    let operator_implementation = operator::OperatorImpl::default();
+    // Server reflection allows grpcurl and similar tools to inspect the
+    // plugin without having the protobuf definitions at hand
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(cnpg::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()?;
 
-    Server::builder()
+    let mut router = Server::builder()
+        .add_service(health_service)
+        .add_service(reflection_service)
         .add_service(cnpg::identity_server::IdentityServer::new(
-            identity_implementation,
-        ))
-        .add_service(
+            identity::IdentityImpl::new(state.clone(), registry.clone()),
+        ));
+    let mut served_services =
+        vec![<cnpg::identity_server::IdentityServer<identity::IdentityImpl> as NamedService>::NAME];
+
+    #[cfg(feature = "lifecycle")]
+    if registry.is_enabled(capabilities::Service::Lifecycle) {
+        router = router.add_service(
             cnpg::operator_lifecycle_server::OperatorLifecycleServer::new(
-                operator_lifecycle_implementation,
+                operator_lifecycle::OperatorLifecycleImpl::default(),
             ),
-        )
-        .add_service(cnpg::operator_server::OperatorServer::new(
-            operator_implementation,
-        ))
-        .serve_with_incoming(uds_stream)
-        .await?;
+        );
+        served_services.push(
+            <cnpg::operator_lifecycle_server::OperatorLifecycleServer<
+                operator_lifecycle::OperatorLifecycleImpl,
+            > as NamedService>::NAME,
+        );
+    }
+
+    #[cfg(feature = "operator")]
+    if registry.is_enabled(capabilities::Service::Operator) {
+        router = router.add_service(cnpg::operator_server::OperatorServer::new(
+            operator::OperatorImpl::new(registry.clone(), state.clone()),
+        ));
+        served_services.push(
+            <cnpg::operator_server::OperatorServer<operator::OperatorImpl> as NamedService>::NAME,
+        );
+    }
+
+    #[cfg(feature = "reconciler")]
+    if registry.is_enabled(capabilities::Service::Reconciler) {
+        router = router.add_service(cnpg::reconciler_hooks_server::ReconcilerHooksServer::new(
+            reconciler::ReconcilerImpl::new(state.clone()),
+        ));
+        served_services.push(<cnpg::reconciler_hooks_server::ReconcilerHooksServer<
+            reconciler::ReconcilerImpl,
+        > as NamedService>::NAME);
+    }
+
+    tokio::spawn(
+        state
+            .clone()
+            .report_health(health_reporter, served_services),
+    );
+
+    let uds = UnixListener::bind(path)?;
+    let uds_stream = UnixListenerStream::new(uds);
+    state.mark_server_initialized();
+    router.serve_with_incoming(uds_stream).await?;
 
     Ok(())
 }
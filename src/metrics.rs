@@ -0,0 +1,187 @@
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// METRICS is the set of metrics exposed by the plugin
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Metrics contains the metrics describing the internal state
+/// of the plugin
+#[derive(Debug, Default)]
+pub struct Metrics {
+    ready: AtomicI64,
+    not_ready_total: Mutex<BTreeMap<String, u64>>,
+    kubernetes_api_unreachable: AtomicI64,
+    policy_reload_failed: AtomicI64,
+    policy_reload_errors_total: AtomicI64,
+}
+
+impl Metrics {
+    /// set_ready records the outcome of the last readiness probe
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready as i64, Ordering::Relaxed);
+    }
+
+    /// inc_not_ready counts a probe which was answered as not ready
+    /// because of the passed reason
+    pub fn inc_not_ready(&self, reason: &str) {
+        let mut not_ready_total = self.not_ready_total.lock().unwrap();
+        *not_ready_total.entry(reason.to_string()).or_default() += 1;
+    }
+
+    /// set_kubernetes_api_unreachable records whether the last check of the
+    /// Kubernetes API failed, which makes the plugin degraded
+    pub fn set_kubernetes_api_unreachable(&self, unreachable: bool) {
+        self.kubernetes_api_unreachable
+            .store(unreachable as i64, Ordering::Relaxed);
+    }
+
+    /// set_policy_reload_failed records whether the last reload of the
+    /// policy file failed, keeping the previous policy
+    pub fn set_policy_reload_failed(&self, failed: bool) {
+        self.policy_reload_failed
+            .store(failed as i64, Ordering::Relaxed);
+    }
+
+    /// inc_policy_reload_errors counts a reload of the policy file which
+    /// failed
+    pub fn inc_policy_reload_errors(&self) {
+        self.policy_reload_errors_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// render encodes the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut result = String::new();
+
+        let _ = writeln!(
+            result,
+            "# HELP cnpg_generic_exporter_plugin_ready Whether the last readiness probe succeeded"
+        );
+        let _ = writeln!(result, "# TYPE cnpg_generic_exporter_plugin_ready gauge");
+        let _ = writeln!(
+            result,
+            "cnpg_generic_exporter_plugin_ready {}",
+            self.ready.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            result,
+            "# HELP cnpg_generic_exporter_plugin_not_ready_total Readiness probes answered as not ready, by reason"
+        );
+        let _ = writeln!(
+            result,
+            "# TYPE cnpg_generic_exporter_plugin_not_ready_total counter"
+        );
+        for (reason, count) in self.not_ready_total.lock().unwrap().iter() {
+            let _ = writeln!(
+                result,
+                "cnpg_generic_exporter_plugin_not_ready_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+
+        let _ = writeln!(
+            result,
+            "# HELP cnpg_generic_exporter_plugin_kubernetes_api_unreachable Whether the last check of the Kubernetes API failed, degrading the plugin"
+        );
+        let _ = writeln!(
+            result,
+            "# TYPE cnpg_generic_exporter_plugin_kubernetes_api_unreachable gauge"
+        );
+        let _ = writeln!(
+            result,
+            "cnpg_generic_exporter_plugin_kubernetes_api_unreachable {}",
+            self.kubernetes_api_unreachable.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            result,
+            "# HELP cnpg_generic_exporter_plugin_policy_reload_failed Whether the last reload of the policy file failed, keeping the previous policy"
+        );
+        let _ = writeln!(
+            result,
+            "# TYPE cnpg_generic_exporter_plugin_policy_reload_failed gauge"
+        );
+        let _ = writeln!(
+            result,
+            "cnpg_generic_exporter_plugin_policy_reload_failed {}",
+            self.policy_reload_failed.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            result,
+            "# HELP cnpg_generic_exporter_plugin_policy_reload_errors_total Reloads of the policy file which failed"
+        );
+        let _ = writeln!(
+            result,
+            "# TYPE cnpg_generic_exporter_plugin_policy_reload_errors_total counter"
+        );
+        let _ = writeln!(
+            result,
+            "cnpg_generic_exporter_plugin_policy_reload_errors_total {}",
+            self.policy_reload_errors_total.load(Ordering::Relaxed)
+        );
+
+        result
+    }
+}
+
+/// serve exposes the plugin metrics over HTTP on the passed address.
+/// Every request, regardless of its path, gets the metrics in response.
+pub async fn serve(address: String) -> std::io::Result<()> {
+    let listener = TcpListener::bind(&address).await?;
+    info!("Serving metrics on {}", address);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            // We don't care about the request content, we just need
+            // to consume it before answering
+            let mut buffer = [0u8; 1024];
+            if let Err(err) = stream.read(&mut buffer).await {
+                warn!("Error while reading metrics request: {}", err);
+                return;
+            }
+
+            let body = METRICS.render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            if let Err(err) = stream.write_all(response.as_bytes()).await {
+                warn!("Error while writing metrics response: {}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.set_ready(false);
+        metrics.inc_not_ready("server_not_initialized");
+        metrics.inc_not_ready("server_not_initialized");
+        metrics.set_kubernetes_api_unreachable(true);
+        metrics.set_policy_reload_failed(true);
+        metrics.inc_policy_reload_errors();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("cnpg_generic_exporter_plugin_ready 0\n"));
+        assert!(rendered.contains(
+            "cnpg_generic_exporter_plugin_not_ready_total{reason=\"server_not_initialized\"} 2\n"
+        ));
+        assert!(rendered.contains("cnpg_generic_exporter_plugin_kubernetes_api_unreachable 1\n"));
+        assert!(rendered.contains("cnpg_generic_exporter_plugin_policy_reload_failed 1\n"));
+        assert!(rendered.contains("cnpg_generic_exporter_plugin_policy_reload_errors_total 1\n"));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    config::{ExporterConfig, NetworkPolicySettings},
+    helper::DataLoader,
+    resources::{self, ClusterRef, ResourceError},
+};
+use k8s_openapi::{
+    api::networking::v1 as networking,
+    apimachinery::pkg::{
+        apis::meta::v1::{LabelSelector, ObjectMeta},
+        util::intstr::IntOrString,
+    },
+};
+use kube::api::{Api, DeleteParams};
+use std::collections::BTreeMap;
+
+/// policy_name is the name of the NetworkPolicy allowing Prometheus
+/// to reach the exporters of a cluster
+fn policy_name(cluster: &str) -> String {
+    format!("{}-exporter", cluster)
+}
+
+/// build_network_policy creates the NetworkPolicy allowing the selected
+/// Pods to reach the exporter ports of the instances of a cluster
+fn build_network_policy(
+    loader: &DataLoader,
+    settings: &NetworkPolicySettings,
+    exporters: &[ExporterConfig],
+) -> networking::NetworkPolicy {
+    let cluster = ClusterRef::from_loader(loader);
+
+    networking::NetworkPolicy {
+        metadata: ObjectMeta {
+            name: Some(policy_name(cluster.name)),
+            namespace: Some(cluster.namespace.to_string()),
+            labels: Some(cluster.labels()),
+            owner_references: resources::owner_references(loader),
+            ..Default::default()
+        },
+        spec: Some(networking::NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(BTreeMap::from([
+                    ("cnpg.io/cluster".to_string(), cluster.name.to_string()),
+                    ("cnpg.io/podRole".to_string(), "instance".to_string()),
+                ])),
+                ..Default::default()
+            },
+            policy_types: Some(vec!["Ingress".to_string()]),
+            ingress: Some(vec![networking::NetworkPolicyIngressRule {
+                from: Some(vec![networking::NetworkPolicyPeer {
+                    namespace_selector: settings.namespace_selector.clone(),
+                    pod_selector: settings.pod_selector.clone(),
+                    ..Default::default()
+                }]),
+                ports: Some(
+                    exporters
+                        .iter()
+                        .map(|exporter| networking::NetworkPolicyPort {
+                            port: Some(IntOrString::Int(exporter.port.into())),
+                            protocol: Some("TCP".to_string()),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+            }]),
+            ..Default::default()
+        }),
+    }
+}
+
+/// reconcile creates or updates the NetworkPolicy allowing Prometheus to
+/// reach the exporters, removing it when it is no longer requested
+pub async fn reconcile(
+    client: &kube::Client,
+    loader: &DataLoader,
+    settings: Option<&NetworkPolicySettings>,
+    exporters: &[ExporterConfig],
+) -> Result<(), ResourceError> {
+    let cluster = ClusterRef::from_loader(loader);
+    let Some(settings) = settings else {
+        return Ok(remove(client, &cluster).await?);
+    };
+
+    let policy = build_network_policy(loader, settings, exporters);
+    let api: Api<networking::NetworkPolicy> = Api::namespaced(client.clone(), cluster.namespace);
+    resources::apply(
+        &api,
+        &cluster,
+        "NetworkPolicy",
+        &policy_name(cluster.name),
+        &policy,
+    )
+    .await
+}
+
+/// remove deletes the NetworkPolicy of a cluster. A NetworkPolicy with
+/// the same name not created by the plugin is left untouched
+pub async fn remove(client: &kube::Client, cluster: &ClusterRef<'_>) -> Result<(), kube::Error> {
+    let api: Api<networking::NetworkPolicy> = Api::namespaced(client.clone(), cluster.namespace);
+    let name = policy_name(cluster.name);
+    match api.get_opt(&name).await? {
+        Some(policy) if cluster.is_owner(policy.metadata.labels.as_ref()) => {
+            api.delete(&name, &DeleteParams::default()).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_build_network_policy() {
+        let loader = DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "sql-exporter-config",
+            "networkPolicy": "podSelector: {matchLabels: {app: prometheus}}"
+        }));
+        let exporters = config::load_exporters(&loader).unwrap();
+        let settings = config::load_scrape_settings(&loader).unwrap();
+        let policy = build_network_policy(
+            &loader,
+            settings.network_policy.as_ref().unwrap(),
+            &exporters,
+        );
+
+        let spec = policy.spec.unwrap();
+        assert_eq!(
+            spec.pod_selector.match_labels.unwrap()["cnpg.io/cluster"],
+            "cluster-example"
+        );
+        let rule = &spec.ingress.unwrap()[0];
+        let peer = &rule.from.as_ref().unwrap()[0];
+        assert!(peer.namespace_selector.is_none());
+        assert_eq!(
+            peer.pod_selector
+                .as_ref()
+                .unwrap()
+                .match_labels
+                .as_ref()
+                .unwrap()["app"],
+            "prometheus"
+        );
+        assert_eq!(
+            rule.ports.as_ref().unwrap()[0].port,
+            Some(IntOrString::Int(9237))
+        );
+    }
+}
@@ -1,46 +1,173 @@
 use crate::{
+    capabilities::{Registry, Service},
     cnpg::{self},
-    helper::DataLoader,
+    config::{self, ExporterConfig, ParameterError},
+    database::{self, DatabaseStatus},
+    defaults::{self, Defaults, Source},
+    error::Error,
+    events, exporter,
+    helper::{self, DataLoader, DataLoaderError},
+    network_policy,
+    policy::{self, Policy},
+    postgresql_settings,
+    resources::ClusterRef,
+    service,
+    state::PluginState,
+};
+use log::{error, warn};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
 };
 use tonic::{Request, Response, Status};
 
-#[derive(Debug, Default)]
-pub struct OperatorImpl {}
+/// register adds the operator service and its RPCs to the registry
+pub fn register(registry: &mut Registry) {
+    if !registry.register(Service::Operator) {
+        return;
+    }
+
+    registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::ValidateClusterCreate);
+    registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::ValidateClusterChange);
+    registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::MutateCluster);
+    registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::SetStatusInCluster);
+    registry.register_operator_rpc(cnpg::operator_capability::rpc::Type::Deregister);
+}
+
+/// PluginStatus is the status reported inside the Cluster resource
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct PluginStatus {
+    /// exporters are the effective settings of the exporter sidecars
+    exporters: Vec<ExporterStatus>,
+
+    /// databases are the outcome of the creation of the monitoring
+    /// extensions in the databases
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    databases: Vec<DatabaseStatus>,
+
+    /// errors are the reasons why the exporters can't be configured
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+
+    /// warnings are the parameters that are valid, but should be improved
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+
+    /// provenance is the source of the value of every parameter
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    provenance: BTreeMap<String, Source>,
+}
+
+/// Severity tells if a finding of the validation blocks the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    /// Error rejects the cluster
+    Error,
+
+    /// Warning is surfaced without blocking the cluster
+    Warning,
+}
+
+/// Finding is a problem found while validating the plugin parameters
+#[derive(Debug, Clone)]
+struct Finding {
+    severity: Severity,
+    error: ParameterError,
+}
+
+/// ExporterStatus is the effective configuration of an exporter sidecar
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExporterStatus {
+    name: String,
+    exporter_type: String,
+    image: String,
+    port: u16,
+    log_level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_format: Option<String>,
+}
+
+impl From<&ExporterConfig> for ExporterStatus {
+    fn from(config: &ExporterConfig) -> Self {
+        ExporterStatus {
+            name: config.container_name.clone(),
+            exporter_type: config.backend.name().to_string(),
+            image: config.image.clone(),
+            port: config.port,
+            log_level: config.log_level.clone(),
+            log_format: config.log_format.clone(),
+        }
+    }
+}
+
+pub struct OperatorImpl {
+    registry: Arc<Registry>,
+    state: Arc<PluginState>,
+}
+
+impl OperatorImpl {
+    pub fn new(registry: Arc<Registry>, state: Arc<PluginState>) -> Self {
+        OperatorImpl { registry, state }
+    }
+
+    /// report surfaces the warnings of the validation in the logs and as
+    /// Events of the cluster, returning the errors to be sent to CNPG.
+    /// The warnings already reported for the previous definition of the
+    /// cluster are not repeated
+    async fn report(
+        &self,
+        loader: &DataLoader,
+        findings: Vec<Finding>,
+        previous: &[Finding],
+    ) -> Vec<cnpg::ValidationError> {
+        let mut result = Vec::new();
+        for finding in findings {
+            let repeated = previous.iter().any(|x| x.error == finding.error);
+            let ParameterError { parameter, message } = finding.error;
+            match finding.severity {
+                Severity::Error => {
+                    result.push(loader.create_validation_error(&parameter, &message))
+                }
+                Severity::Warning if repeated => {}
+                Severity::Warning => {
+                    let message = format!("{}: {}", parameter, message);
+                    warn!(
+                        "Warning for the cluster {}: {}",
+                        loader.cluster_name(),
+                        message
+                    );
+                    if let Some(client) = self.state.kubernetes_client() {
+                        if let Err(err) =
+                            events::warning(client, loader, "PluginParameterWarning", &message)
+                                .await
+                        {
+                            error!(
+                                "Error while recording a warning event for {}: {}",
+                                loader.cluster_name(),
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
 
 #[tonic::async_trait]
 impl cnpg::operator_server::Operator for OperatorImpl {
     /// GetCapabilities gets the capabilities of the Lifecycle service
-
     async fn get_capabilities(
         &self,
         _request: Request<cnpg::OperatorCapabilitiesRequest>,
     ) -> Result<Response<cnpg::OperatorCapabilitiesResult>, Status> {
         Ok(Response::new(cnpg::OperatorCapabilitiesResult {
-            capabilities: vec![
-                cnpg::OperatorCapability {
-                    r#type: Some(cnpg::operator_capability::Type::Rpc(
-                        cnpg::operator_capability::Rpc {
-                            r#type: cnpg::operator_capability::rpc::Type::ValidateClusterCreate
-                                .into(),
-                        },
-                    )),
-                },
-                cnpg::OperatorCapability {
-                    r#type: Some(cnpg::operator_capability::Type::Rpc(
-                        cnpg::operator_capability::Rpc {
-                            r#type: cnpg::operator_capability::rpc::Type::ValidateClusterChange
-                                .into(),
-                        },
-                    )),
-                },
-                cnpg::OperatorCapability {
-                    r#type: Some(cnpg::operator_capability::Type::Rpc(
-                        cnpg::operator_capability::Rpc {
-                            r#type: cnpg::operator_capability::rpc::Type::MutateCluster.into(),
-                        },
-                    )),
-                },
-            ],
+            capabilities: self.registry.operator_capabilities(),
         }))
     }
 
@@ -50,16 +177,11 @@ impl cnpg::operator_server::Operator for OperatorImpl {
         &self,
         request: Request<cnpg::OperatorValidateClusterCreateRequest>,
     ) -> Result<Response<cnpg::OperatorValidateClusterCreateResult>, Status> {
-        let loader = crate::helper::DataLoader::from_cluster(
-            crate::consts::PLUGIN_NAME,
-            &request.get_ref().definition,
-        )
-        .map_err(|err| {
-            Status::invalid_argument(format!("Error while parsing cluster definition: {}", err))
-        })?;
+        let loader =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, &request.get_ref().definition)?;
 
         Ok(Response::new(cnpg::OperatorValidateClusterCreateResult {
-            validation_errors: validate(&loader),
+            validation_errors: self.report(&loader, validate(&loader), &[]).await,
         }))
     }
 
@@ -69,16 +191,22 @@ impl cnpg::operator_server::Operator for OperatorImpl {
         &self,
         request: Request<cnpg::OperatorValidateClusterChangeRequest>,
     ) -> std::result::Result<Response<cnpg::OperatorValidateClusterChangeResult>, Status> {
-        let loader = crate::helper::DataLoader::from_cluster(
-            crate::consts::PLUGIN_NAME,
-            &request.get_ref().new_cluster,
-        )
-        .map_err(|err| {
-            Status::invalid_argument(format!("Error while parsing cluster definition: {}", err))
-        })?;
+        let loader =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, &request.get_ref().new_cluster)?;
+
+        // The plugin may have just been added to the cluster, in which case
+        // there's nothing to compare with
+        let mut findings = validate(&loader);
+        let mut previous = Vec::new();
+        if let Ok(old_loader) =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, &request.get_ref().old_cluster)
+        {
+            findings.extend(validate_change(&old_loader, &loader));
+            previous = validate(&old_loader);
+        }
 
         Ok(Response::new(cnpg::OperatorValidateClusterChangeResult {
-            validation_errors: validate(&loader),
+            validation_errors: self.report(&loader, findings, &previous).await,
         }))
     }
 
@@ -87,66 +215,507 @@ impl cnpg::operator_server::Operator for OperatorImpl {
         &self,
         request: Request<cnpg::OperatorMutateClusterRequest>,
     ) -> Result<Response<cnpg::OperatorMutateClusterResult>, Status> {
-        let loader = crate::helper::DataLoader::from_cluster(
-            crate::consts::PLUGIN_NAME,
-            &request.get_ref().definition,
-        )
-        .map_err(|err| {
-            Status::invalid_argument(format!("Error while parsing cluster definition: {}", err))
-        })?;
+        let loader =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, &request.get_ref().definition)?;
 
-        let mut new_parameters = loader.copy_parameters();
-        new_parameters
-            .entry("imageName".to_string())
-            .or_insert(crate::consts::IMAGE_NAME_PARAMETER_DEFAULT.to_string());
+        // The namespace defaults are optional, and can't be read without
+        // the Kubernetes API. A transient error while reading them must not
+        // block the Clusters of the namespace, so the policy and the plugin
+        // defaults are used instead
+        let mut namespace_defaults = HashMap::new();
+        if let Some(client) = self.state.kubernetes_client() {
+            let namespace = ClusterRef::from_loader(&loader).namespace;
+            match defaults::namespace_defaults(client, namespace).await {
+                Ok(values) => namespace_defaults = values,
+                Err(err) => warn!(
+                    "Error while reading the namespace defaults of {}, using the other defaults: {}",
+                    loader.cluster_name(),
+                    err
+                ),
+            }
+        }
 
-        let patch_value = loader
-            .calculate_cluster_patch(&new_parameters)
-            .map_err(|e| Status::internal(format!("Error while calculating JSON patch: {}", e)))?;
-        let serialized_patch: String = serde_json::to_string(&patch_value)
-            .map_err(|e| Status::internal(format!("While serializing patch: {}", e)))?;
+        let patch_value = mutate(&loader, namespace_defaults, &policy::POLICY.get())?;
+        let serialized_patch: String =
+            serde_json::to_string(&patch_value).map_err(|source| Error::Serialization {
+                context: "cluster patch".to_string(),
+                source,
+            })?;
 
         Ok(Response::new(cnpg::OperatorMutateClusterResult {
             json_patch: serialized_patch.into_bytes(),
         }))
     }
 
+    /// SetStatusInCluster reports the effective settings of the exporters
+    /// in the status of the Cluster resource
     async fn set_status_in_cluster(
         &self,
-        _: tonic::Request<cnpg::SetStatusInClusterRequest>,
-    ) -> std::result::Result<
-        tonic::Response<cnpg::SetStatusInClusterResponse>,
-        tonic::Status,
-    > {
-        Ok(Response::new(cnpg::SetStatusInClusterResponse{
-            json_status: vec![],
+        request: tonic::Request<cnpg::SetStatusInClusterRequest>,
+    ) -> std::result::Result<tonic::Response<cnpg::SetStatusInClusterResponse>, tonic::Status> {
+        let loader =
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, &request.get_ref().cluster)?;
+
+        let mut status = status(&loader);
+        status.provenance = defaults::provenance(&loader);
+        status
+            .errors
+            .extend(self.state.reconcile_errors(loader.cluster_name()));
+        if let Some(client) = self.state.kubernetes_client() {
+            match database::status(client, &loader).await {
+                Ok(databases) => status.databases = databases,
+                Err(err) => status
+                    .errors
+                    .push(format!("cannot read the monitoring databases: {}", err)),
+            }
+        }
+
+        let json_status = serde_json::to_vec(&status).map_err(|source| Error::Serialization {
+            context: "plugin status".to_string(),
+            source,
+        })?;
+
+        Ok(Response::new(cnpg::SetStatusInClusterResponse {
+            json_status,
         }))
     }
 
+    /// Deregister removes the resources created by the plugin for
+    /// a cluster which is no longer using it
     async fn deregister(
         &self,
-        _: tonic::Request<cnpg::DeregisterRequest>,
-    ) -> std::result::Result<
-        tonic::Response<cnpg::DeregisterResponse>,
-        tonic::Status,
-    > {
-        Ok(Response::new(cnpg::DeregisterResponse{
-        }))
+        request: tonic::Request<cnpg::DeregisterRequest>,
+    ) -> std::result::Result<tonic::Response<cnpg::DeregisterResponse>, tonic::Status> {
+        // The plugin is no longer listed in the cluster definition,
+        // so we just need its metadata
+        let cluster: serde_json::Value = serde_json::from_slice(&request.get_ref().definition)
+            .map_err(|err| Error::from(DataLoaderError::from(err)))?;
+        let cluster = ClusterRef {
+            namespace: cluster["metadata"]["namespace"]
+                .as_str()
+                .unwrap_or_default(),
+            name: cluster["metadata"]["name"].as_str().unwrap_or_default(),
+        };
+        let kubernetes_error = |context: &str| {
+            let cluster = format!("{}/{}", cluster.namespace, cluster.name);
+            let context = context.to_string();
+            move |source| Error::Kubernetes {
+                cluster,
+                context,
+                source: Box::new(source),
+            }
+        };
+
+        if let Some(client) = self.state.kubernetes_client() {
+            database::remove(client, &cluster)
+                .await
+                .map_err(kubernetes_error("removing the monitoring databases"))?;
+            service::remove(client, &cluster)
+                .await
+                .map_err(kubernetes_error("removing the exporter service"))?;
+            network_policy::remove(client, &cluster)
+                .await
+                .map_err(kubernetes_error("removing the exporter network policy"))?;
+        }
+        self.state.set_reconcile_errors(
+            &format!("{}/{}", cluster.namespace, cluster.name),
+            Vec::new(),
+        );
+
+        Ok(Response::new(cnpg::DeregisterResponse {}))
     }
 }
 
-fn validate(loader: &DataLoader) -> Vec<cnpg::ValidationError> {
-    let mut res: Vec<cnpg::ValidationError> = Default::default();
+/// status computes the plugin status for a cluster
+fn status(loader: &DataLoader) -> PluginStatus {
+    match config::load_exporters(loader) {
+        Ok(exporters) => PluginStatus {
+            exporters: exporters.iter().map(ExporterStatus::from).collect(),
+            warnings: warnings(loader)
+                .iter()
+                .map(|err| format!("{}: {}", err.parameter, err.message))
+                .collect(),
+            ..Default::default()
+        },
+        Err(errors) => PluginStatus {
+            errors: errors
+                .iter()
+                .map(|err| format!("{}: {}", err.parameter, err.message))
+                .collect(),
+            ..Default::default()
+        },
+    }
+}
 
+/// mutate computes the patch writing the parameters not specified by a
+/// cluster. They are taken from the defaults of the namespace, then from
+/// the policy and finally from the plugin defaults, unless the cluster
+/// opts out of them
+fn mutate(
+    loader: &DataLoader,
+    mut namespace_defaults: HashMap<String, String>,
+    policy: &Policy,
+) -> Result<serde_json::Value, Error> {
+    // The entries of the exporters list inherit the top-level port and
+    // image, so setting them would make the entries share them
+    let mut policy_defaults = policy.default_parameters();
     if loader
-        .get_parameter(crate::consts::CONFIG_MAP_PARAMETER_NAME)
-        .is_none()
+        .get_parameter(crate::consts::EXPORTERS_PARAMETER_NAME)
+        .is_some()
+        || namespace_defaults.contains_key(crate::consts::EXPORTERS_PARAMETER_NAME)
+        || policy_defaults.contains_key(crate::consts::EXPORTERS_PARAMETER_NAME)
     {
-        res.push(loader.create_validation_error(
-            crate::consts::CONFIG_MAP_PARAMETER_NAME,
-            "this parameter is required",
-        ))
+        for values in [&mut namespace_defaults, &mut policy_defaults] {
+            values.remove(crate::consts::PORT_PARAMETER_NAME);
+            values.remove(crate::consts::IMAGE_NAME_PARAMETER_NAME);
+        }
     }
 
-    res
+    let mut defaults = Defaults::new(loader);
+    defaults.fill(Source::Namespace, namespace_defaults);
+    defaults.fill(Source::Policy, policy_defaults);
+
+    // An invalid value is reported by the validation webhook
+    let materialize_defaults = defaults
+        .parameters()
+        .get(crate::consts::MATERIALIZE_DEFAULTS_PARAMETER_NAME)
+        .and_then(|x| helper::parse_bool(x).ok())
+        .unwrap_or(true);
+    if materialize_defaults {
+        defaults.fill(
+            Source::Default,
+            config::default_parameters(defaults.parameters()),
+        );
+
+        // The image depends on the exporter type and version, and we write
+        // its exact reference to make rollouts reproducible. When the type
+        // or the version are not valid the validation webhook will complain
+        // about it. The entries of the exporters list inherit the image, so
+        // we don't set it there
+        let parameters = defaults.parameters();
+        if !parameters.contains_key(crate::consts::EXPORTERS_PARAMETER_NAME) {
+            if let Some(image) = exporter::get_backend(
+                parameters
+                    .get(crate::consts::EXPORTER_TYPE_PARAMETER_NAME)
+                    .map(|x| x.as_str()),
+            )
+            .and_then(|backend| config::resolve_image(backend, parameters).ok())
+            {
+                defaults.set(
+                    Source::Catalog,
+                    crate::consts::IMAGE_NAME_PARAMETER_NAME,
+                    image,
+                );
+            }
+        }
+    }
+
+    // The PostgreSQL settings required by the monitoring queries are merged
+    // with the ones of the cluster. Invalid parameters are reported by the
+    // validation webhook
+    let mut new_cluster = loader.updated_cluster(defaults.parameters(), &defaults.annotations());
+    let settings =
+        config::load_monitoring_settings(&loader.with_parameters(defaults.parameters().clone()))
+            .unwrap_or_default();
+    postgresql_settings::enrich(&settings, &mut new_cluster);
+
+    loader.calculate_cluster_patch(&new_cluster)
+}
+
+/// validate checks the plugin parameters of a cluster
+fn validate(loader: &DataLoader) -> Vec<Finding> {
+    let errors = loader
+        .invalid_parameters()
+        .iter()
+        .map(|(parameter, message)| ParameterError {
+            parameter: parameter.clone(),
+            message: message.clone(),
+        })
+        .chain(
+            config::load_exporters(loader)
+                .err()
+                .into_iter()
+                .chain(config::load_monitoring_settings(loader).err())
+                .chain(config::load_scrape_settings(loader).err())
+                .flatten(),
+        )
+        .chain(
+            loader
+                .get_bool_parameter(crate::consts::MATERIALIZE_DEFAULTS_PARAMETER_NAME)
+                .err()
+                .map(|message| ParameterError {
+                    parameter: crate::consts::MATERIALIZE_DEFAULTS_PARAMETER_NAME.to_string(),
+                    message,
+                }),
+        )
+        .chain(policy::POLICY.get().check(loader))
+        .map(|error| Finding {
+            severity: Severity::Error,
+            error,
+        });
+    let warnings = warnings(loader).into_iter().map(|error| Finding {
+        severity: Severity::Warning,
+        error,
+    });
+
+    errors.chain(warnings).collect()
+}
+
+/// warnings are the parameter values which are accepted, but should be
+/// improved
+fn warnings(loader: &DataLoader) -> Vec<ParameterError> {
+    let mut result = config::load_warnings(loader);
+    result.extend(postgresql_settings::warnings(loader));
+    result
+}
+
+/// validate_change checks the changes of the plugin parameters, which
+/// are rejected when they can't be applied to a running cluster
+fn validate_change(old: &DataLoader, new: &DataLoader) -> Vec<Finding> {
+    let check = config::check_change(old, new);
+    let rejected = check.rejected.into_iter().map(|error| Finding {
+        severity: Severity::Error,
+        error,
+    });
+    let risky = check.risky.into_iter().map(|error| Finding {
+        severity: Severity::Warning,
+        error,
+    });
+
+    rejected.chain(risky).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let value = serde_json::to_value(status(&DataLoader::from_parameters(
+            serde_json::json!({"configMapName": "config", "exporterLogLevel": "debug"}),
+        )))
+        .unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "exporters": [{
+                    "name": "sql-exporter",
+                    "exporterType": "justwatchcom-sql-exporter",
+                    "image": "ghcr.io/justwatchcom/sql_exporter:v0.5",
+                    "port": 9237,
+                    "logLevel": "debug"
+                }],
+                "warnings": [
+                    "resources: no resource limits are set for the exporter container"
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_severity() {
+        let findings = validate(&DataLoader::from_parameters(
+            serde_json::json!({"configMapName": "config"}),
+        ));
+        assert!(!findings.is_empty());
+        assert!(findings.iter().all(|x| x.severity == Severity::Warning));
+
+        let findings = validate(&DataLoader::from_parameters(serde_json::json!({})));
+        assert!(findings.iter().any(|x| x.severity == Severity::Error));
+
+        let findings = validate(&DataLoader::from_parameters(
+            serde_json::json!({"configMapName": "config", "port": null}),
+        ));
+        assert!(findings
+            .iter()
+            .any(|x| x.severity == Severity::Error && x.error.parameter == "port"));
+    }
+
+    /// mutate_patch is the patch computed by mutate for a cluster
+    /// with the passed parameters
+    fn mutate_patch(parameters: serde_json::Value) -> serde_json::Value {
+        mutate(
+            &DataLoader::from_parameters(parameters),
+            HashMap::new(),
+            &Policy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_mutate_materializes_defaults() {
+        assert_eq!(
+            mutate_patch(serde_json::json!({"configMapName": "config"})),
+            serde_json::json!([
+                {
+                    "op": "add",
+                    "path": "/metadata/annotations",
+                    "value": {
+                        crate::consts::DEFAULTS_ANNOTATION: concat!(
+                            r#"{"allowEnvOverride":{"source":"default","value":"false"},"#,
+                            r#""createService":{"source":"default","value":"false"},"#,
+                            r#""enableStatStatements":{"source":"default","value":"false"},"#,
+                            r#""enableTrackIoTiming":{"source":"default","value":"false"},"#,
+                            r#""exporterLogLevel":{"source":"default","value":"info"},"#,
+                            r#""exporterType":{"source":"default","value":"justwatchcom-sql-exporter"},"#,
+                            r#""imageName":{"source":"catalog","value":"ghcr.io/justwatchcom/sql_exporter:v0.5"},"#,
+                            r#""monitoringExtensions":{"source":"default","value":"[\"pg_stat_statements\"]"},"#,
+                            r#""port":{"source":"default","value":"9237"},"#,
+                            r#""scrapeAnnotations":{"source":"default","value":"false"},"#,
+                            r#""scratchDataMount":{"source":"default","value":"socket"}}"#
+                        )
+                    }
+                },
+                {"op": "add", "path": "/spec/plugins/0/parameters/allowEnvOverride", "value": "false"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/createService", "value": "false"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/enableStatStatements", "value": "false"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/enableTrackIoTiming", "value": "false"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/exporterLogLevel", "value": "info"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/exporterType", "value": "justwatchcom-sql-exporter"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/imageName", "value": "ghcr.io/justwatchcom/sql_exporter:v0.5"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/monitoringExtensions", "value": "[\"pg_stat_statements\"]"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/port", "value": "9237"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/scrapeAnnotations", "value": "false"},
+                {"op": "add", "path": "/spec/plugins/0/parameters/scratchDataMount", "value": "socket"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mutate_without_materialized_defaults() {
+        assert_eq!(
+            mutate_patch(serde_json::json!({
+                "configMapName": "config",
+                "materializeDefaults": "false"
+            })),
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn test_mutate_enriches_postgresql() {
+        let patch = mutate_patch(serde_json::json!({
+            "configMapName": "config",
+            "materializeDefaults": "false",
+            "enableStatStatements": "true",
+            "enableTrackIoTiming": "true"
+        }));
+
+        assert!(patch.as_array().unwrap().contains(&serde_json::json!({
+            "op": "add",
+            "path": "/spec/postgresql",
+            "value": {
+                "shared_preload_libraries": ["pg_stat_statements"],
+                "parameters": {"track_io_timing": "on"}
+            }
+        })));
+    }
+
+    #[test]
+    fn test_mutate_is_stable() {
+        let mut cluster = serde_json::json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Cluster",
+            "metadata": {"name": "cluster-example", "namespace": "default"},
+            "spec": {
+                "plugins": [{
+                    "name": crate::consts::PLUGIN_NAME,
+                    "parameters": {"configMapName": "config", "port": 9300}
+                }]
+            }
+        });
+        let load = |cluster: &serde_json::Value| {
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, cluster.to_string().as_bytes())
+                .unwrap()
+        };
+
+        let patch = mutate(&load(&cluster), HashMap::new(), &Policy::default()).unwrap();
+        let patch: json_patch::Patch = serde_json::from_value(patch).unwrap();
+        json_patch::patch(&mut cluster, &patch).unwrap();
+        assert_eq!(cluster["spec"]["plugins"][0]["parameters"]["port"], 9300);
+
+        assert_eq!(
+            mutate(&load(&cluster), HashMap::new(), &Policy::default()).unwrap(),
+            serde_json::json!([])
+        );
+        assert_eq!(
+            defaults::provenance(&load(&cluster))["exporterLogLevel"],
+            Source::Default
+        );
+    }
+
+    #[test]
+    fn test_mutate_keeps_invalid_values() {
+        let mut cluster = serde_json::json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Cluster",
+            "metadata": {"name": "cluster-example", "namespace": "default"},
+            "spec": {
+                "plugins": [{
+                    "name": crate::consts::PLUGIN_NAME,
+                    "parameters": {"configMapName": "config", "port": null, "foo": null}
+                }]
+            }
+        });
+        let load = |cluster: &serde_json::Value| {
+            DataLoader::from_cluster(crate::consts::PLUGIN_NAME, cluster.to_string().as_bytes())
+                .unwrap()
+        };
+
+        let patch = mutate(&load(&cluster), HashMap::new(), &Policy::default()).unwrap();
+        let patch: json_patch::Patch = serde_json::from_value(patch).unwrap();
+        json_patch::patch(&mut cluster, &patch).unwrap();
+        let parameters = &cluster["spec"]["plugins"][0]["parameters"];
+        assert_eq!(parameters["port"], serde_json::Value::Null);
+        assert!(parameters.as_object().unwrap().contains_key("foo"));
+
+        let errors: Vec<_> = validate(&load(&cluster))
+            .into_iter()
+            .filter(|x| x.severity == Severity::Error)
+            .map(|x| x.error.parameter)
+            .collect();
+        assert_eq!(errors, vec!["foo", "port"]);
+    }
+
+    #[test]
+    fn test_mutate_exporters_list() {
+        let patch = mutate(
+            &DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "exporters": r#"[{"name": "one"}, {"name": "two", "port": 9300}]"#
+            })),
+            HashMap::from([
+                ("port".to_string(), "9400".to_string()),
+                ("imageName".to_string(), "team/sql_exporter:0.5".to_string()),
+            ]),
+            &Policy::default(),
+        )
+        .unwrap();
+
+        let paths: Vec<&str> = patch
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["path"].as_str().unwrap())
+            .collect();
+        assert!(paths.contains(&"/spec/plugins/0/parameters/exporterLogLevel"));
+        assert!(!paths.contains(&"/spec/plugins/0/parameters/port"));
+        assert!(!paths.contains(&"/spec/plugins/0/parameters/imageName"));
+    }
+
+    #[test]
+    fn test_status_with_errors() {
+        let value =
+            serde_json::to_value(status(&DataLoader::from_parameters(serde_json::json!({}))))
+                .unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "exporters": [],
+                "errors": ["configMapName: this parameter is required"]
+            })
+        );
+    }
 }
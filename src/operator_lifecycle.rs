@@ -1,9 +1,21 @@
-use crate::cnpg;
-use json_patch;
+use crate::{
+    capabilities::{Registry, Service},
+    cnpg,
+    config::{self, ExporterConfig, InstanceRole, ParameterError, ScratchDataMount},
+    error::Error,
+    exporter,
+    helper::DataLoader,
+};
 use k8s_openapi::api::core::v1 as api;
 use log::debug;
+use std::collections::BTreeMap;
 use tonic::{Request, Response, Status};
 
+/// register adds the lifecycle service to the registry
+pub fn register(registry: &mut Registry) {
+    registry.register(Service::Lifecycle);
+}
+
 #[derive(Debug, Default)]
 pub struct OperatorLifecycleImpl {}
 
@@ -32,128 +44,581 @@ impl cnpg::operator_lifecycle_server::OperatorLifecycle for OperatorLifecycleImp
         request: Request<cnpg::OperatorLifecycleRequest>,
     ) -> std::result::Result<Response<cnpg::OperatorLifecycleResponse>, Status> {
         // We get and parse the cluster definition
-        let helper = crate::helper::DataLoader::from_cluster(
+        let helper = DataLoader::from_cluster(
             crate::consts::PLUGIN_NAME,
             &request.get_ref().cluster_definition,
-        )
-        .map_err(|err| {
-            Status::internal(format!(
-                "While decoding cluster definition: {}",
-                err.to_string()
-            ))
-        })?;
+        )?;
 
         // When this method is called, cloudnative-pg is creating a Pod.
         // Let's inject the generic exporter sidecar here.
-        let original_pod: api::Pod = serde_json::from_slice(&request.get_ref().object_definition)
-            .map_err(|err| Status::internal(err.to_string()))?;
+        let invalid_pod = |err: serde_json::Error| Error::InvalidPod {
+            cluster: helper.cluster_name().to_string(),
+            message: err.to_string(),
+        };
+        let original_pod: api::Pod =
+            serde_json::from_slice(&request.get_ref().object_definition).map_err(invalid_pod)?;
 
         let mut pod: api::Pod = original_pod.clone();
 
-        // Create a generic exporter Sidecar
-        let mut generic_exporter_sidecar: api::Container = Default::default();
-        generic_exporter_sidecar.name = "sql-exporter".to_string();
-        generic_exporter_sidecar.image = Some(
-            helper
-                .get_parameter(crate::consts::IMAGE_NAME_PARAMETER_NAME)
-                .unwrap_or(crate::consts::IMAGE_NAME_PARAMETER_DEFAULT.to_string())
-                .to_string(),
+        inject_exporters(&helper, &mut pod)?;
+
+        // Create the json patch
+        let patch = json_patch::diff(
+            &serde_json::to_value(original_pod).map_err(|source| Error::Serialization {
+                context: "CNPG pod [original]".to_string(),
+                source,
+            })?,
+            &serde_json::to_value(pod).map_err(|source| Error::Serialization {
+                context: "CNPG pod [new]".to_string(),
+                source,
+            })?,
         );
-        generic_exporter_sidecar.env = Some(vec![
-            api::EnvVar {
-                name: "CONFIG".to_string(),
-                value: Some("/config/config.yml".to_string()),
-                value_from: None,
-            },
-            api::EnvVar {
-                name: "LOGLEVEL".to_string(),
-                value: Some("info".to_string()),
-                value_from: None,
-            },
-        ]);
-        generic_exporter_sidecar.volume_mounts = Some(vec![
-            api::VolumeMount {
-                mount_path: "/config".to_string(),
-                mount_propagation: None,
-                name: "sql-exporter-configuration".to_string(),
-                read_only: Some(true),
-                sub_path: None,
-                sub_path_expr: None,
-            },
-            api::VolumeMount {
-                mount_path: "/controller".to_string(),
-                mount_propagation: None,
-                name: "scratch-data".to_string(),
-                read_only: None,
-                sub_path: None,
-                sub_path_expr: None,
-            },
-            api::VolumeMount {
-                mount_path: "/run".to_string(),
-                mount_propagation: None,
-                name: "scratch-data".to_string(),
-                read_only: None,
-                sub_path: None,
-                sub_path_expr: None,
-            },
-        ]);
-        generic_exporter_sidecar.restart_policy = Some("Always".to_string());
-
-        // Create a volume for the exporter configuration
-        let mut exporter_configuration_volume: api::Volume = Default::default();
-        exporter_configuration_volume.name = "sql-exporter-configuration".to_string();
-        exporter_configuration_volume.config_map = Some(api::ConfigMapVolumeSource {
+
+        let serialized_patch =
+            serde_json::to_string(&patch).map_err(|source| Error::Serialization {
+                context: "pod patch".to_string(),
+                source,
+            })?;
+
+        debug!("Serialized patch: {}", serialized_patch);
+
+        return Ok(Response::new(cnpg::OperatorLifecycleResponse {
+            json_patch: serialized_patch.into_bytes(),
+        }));
+    }
+}
+
+/// parameter_error reports the invalid plugin parameters to CNPG
+fn parameter_error(helper: &DataLoader, errors: Vec<ParameterError>) -> Error {
+    helper.create_parameter_error(
+        &errors[0].parameter,
+        &errors
+            .iter()
+            .map(|x| x.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; "),
+    )
+}
+
+/// inject_exporters adds the exporter sidecars and their volumes to a Pod
+fn inject_exporters(helper: &DataLoader, pod: &mut api::Pod) -> Result<(), Error> {
+    let invalid_pod = |message: &str| Error::InvalidPod {
+        cluster: helper.cluster_name().to_string(),
+        message: message.to_string(),
+    };
+
+    let exporters =
+        config::load_exporters(helper).map_err(|errors| parameter_error(helper, errors))?;
+    let scrape_settings =
+        config::load_scrape_settings(helper).map_err(|errors| parameter_error(helper, errors))?;
+
+    // The configuration is selected when the Pod is created: after a
+    // switchover the Pod keeps it until it is recreated. The live role
+    // is available to the exporter through the downward API
+    let role = if pod.metadata.name.as_deref() == Some(helper.target_primary().as_str()) {
+        InstanceRole::Primary
+    } else {
+        InstanceRole::Replica
+    };
+    pod.metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .insert(
+            crate::consts::CONFIG_ROLE_LABEL.to_string(),
+            role.as_str().to_string(),
+        );
+    if scrape_settings.annotations {
+        annotate_pod(&exporters[0], pod);
+    }
+
+    // Inject the sidecars and their configuration volumes
+    let spec = pod
+        .spec
+        .as_mut()
+        .ok_or_else(|| invalid_pod("CNPG Pod without spec?"))?;
+    let used_container_names: Vec<String> = spec
+        .containers
+        .iter()
+        .chain(spec.init_containers.iter().flatten())
+        .map(|x| x.name.clone())
+        .collect();
+    let init_containers = spec
+        .init_containers
+        .as_mut()
+        .ok_or_else(|| invalid_pod("CNPG Pod without init containers?"))?;
+    let volumes = spec
+        .volumes
+        .as_mut()
+        .ok_or_else(|| invalid_pod("CNPG Pod without volumes?"))?;
+    let pod_volume_names: Vec<String> = volumes.iter().map(|x| x.name.clone()).collect();
+    if !pod_volume_names
+        .iter()
+        .any(|x| x == exporter::SCRATCH_DATA_VOLUME_NAME)
+    {
+        return Err(invalid_pod(&format!(
+            "CNPG Pod without {} volume?",
+            exporter::SCRATCH_DATA_VOLUME_NAME
+        )));
+    }
+    if volumes
+        .iter()
+        .any(|x| x.name == exporter::PODINFO_VOLUME_NAME)
+    {
+        return Err(invalid_pod(&format!(
+            "volume {} already exists",
+            exporter::PODINFO_VOLUME_NAME
+        )));
+    }
+    volumes.push(build_podinfo_volume());
+
+    for exporter_config in &exporters {
+        if used_container_names.contains(&exporter_config.container_name) {
+            return Err(invalid_pod(&format!(
+                "container {} already exists",
+                exporter_config.container_name
+            )));
+        }
+        if volumes
+            .iter()
+            .any(|x| x.name == exporter_config.volume_name)
+        {
+            return Err(invalid_pod(&format!(
+                "volume {} already exists",
+                exporter_config.volume_name
+            )));
+        }
+
+        init_containers.push(build_sidecar(exporter_config, role));
+        volumes.push(build_configuration_volume(exporter_config, role));
+
+        // The additional volumes can be shared by multiple exporters,
+        // as long as they are defined in the same way
+        for volume in &exporter_config.volumes {
+            if pod_volume_names.contains(&volume.name) {
+                return Err(invalid_pod(&format!(
+                    "volume {} already exists",
+                    volume.name
+                )));
+            }
+            match volumes.iter().find(|x| x.name == volume.name) {
+                Some(existing) if existing == volume => {}
+                Some(_) => {
+                    return Err(invalid_pod(&format!(
+                        "volume {} is defined differently by multiple exporters",
+                        volume.name
+                    )))
+                }
+                None => volumes.push(volume.clone()),
+            }
+        }
+    }
+
+    // The pull secrets are shared by every container of the Pod, and
+    // CNPG may have already set some of them
+    for name in exporters.iter().flat_map(|x| &x.image_pull_secrets) {
+        let image_pull_secrets = spec.image_pull_secrets.get_or_insert_with(Vec::new);
+        if !image_pull_secrets
+            .iter()
+            .any(|x| x.name.as_ref() == Some(name))
+        {
+            image_pull_secrets.push(api::LocalObjectReference {
+                name: Some(name.clone()),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// annotate_pod adds the annotations allowing Prometheus annotation-based
+/// discovery to scrape an exporter. They can only describe one port, and
+/// are left untouched when they have already been set, e.g. by the user for
+/// the exporter built into the instance manager
+fn annotate_pod(exporter_config: &ExporterConfig, pod: &mut api::Pod) {
+    let annotations = pod.metadata.annotations.get_or_insert_with(BTreeMap::new);
+    if annotations
+        .keys()
+        .any(|x| x.starts_with(crate::consts::SCRAPE_ANNOTATION_PREFIX))
+    {
+        debug!("Prometheus scrape annotations already set, skipping them");
+        return;
+    }
+
+    annotations.extend(exporter_config.scrape_annotations());
+}
+
+/// build_sidecar creates the container running an exporter
+fn build_sidecar(exporter_config: &ExporterConfig, role: InstanceRole) -> api::Container {
+    let settings = exporter_config.settings();
+
+    let mut volume_mounts = vec![
+        api::VolumeMount {
+            mount_path: exporter::CONFIG_DIRECTORY.to_string(),
+            mount_propagation: None,
+            name: exporter_config.volume_name.clone(),
+            read_only: Some(true),
+            sub_path: None,
+            sub_path_expr: None,
+        },
+        api::VolumeMount {
+            mount_path: exporter::PODINFO_DIRECTORY.to_string(),
+            mount_propagation: None,
+            name: exporter::PODINFO_VOLUME_NAME.to_string(),
+            read_only: Some(true),
+            sub_path: None,
+            sub_path_expr: None,
+        },
+    ];
+    match exporter_config.scratch_data_mount {
+        ScratchDataMount::Socket => volume_mounts.push(api::VolumeMount {
+            mount_path: exporter::POSTGRES_SOCKET_DIRECTORY.to_string(),
+            mount_propagation: None,
+            name: exporter::SCRATCH_DATA_VOLUME_NAME.to_string(),
+            read_only: Some(true),
+            sub_path: Some(exporter::POSTGRES_SOCKET_SUB_PATH.to_string()),
+            sub_path_expr: None,
+        }),
+        ScratchDataMount::Full => volume_mounts.extend(
+            [exporter::CONTROLLER_DIRECTORY, exporter::RUN_DIRECTORY].map(|mount_path| {
+                api::VolumeMount {
+                    mount_path: mount_path.to_string(),
+                    mount_propagation: None,
+                    name: exporter::SCRATCH_DATA_VOLUME_NAME.to_string(),
+                    read_only: None,
+                    sub_path: None,
+                    sub_path_expr: None,
+                }
+            }),
+        ),
+    }
+    volume_mounts.extend(exporter_config.volume_mounts.iter().cloned());
+
+    api::Container {
+        name: exporter_config.container_name.clone(),
+        image: Some(exporter_config.image.clone()),
+        image_pull_policy: exporter_config.image_pull_policy.clone(),
+        env: Some(exporter_config.env(role)),
+        env_from: Some(exporter_config.env_from.clone()).filter(|x| !x.is_empty()),
+        command: exporter_config.command.clone(),
+        args: Some(exporter_config.args()).filter(|args| !args.is_empty()),
+        ports: Some(vec![api::ContainerPort {
+            name: Some(exporter_config.port_name.clone()),
+            container_port: settings.port as i32,
+            protocol: Some("TCP".to_string()),
+            ..Default::default()
+        }]),
+        volume_mounts: Some(volume_mounts),
+        resources: exporter_config.resources.clone(),
+        security_context: exporter_config.security_context.clone(),
+        restart_policy: Some("Always".to_string()),
+        ..Default::default()
+    }
+}
+
+/// build_podinfo_volume creates the downward API volume exposing the
+/// labels and the annotations of the Pod, which are kept up to date
+/// by the kubelet when the instance role changes
+fn build_podinfo_volume() -> api::Volume {
+    let item = |path: &str, field_path: &str| api::DownwardAPIVolumeFile {
+        path: path.to_string(),
+        field_ref: Some(api::ObjectFieldSelector {
+            api_version: None,
+            field_path: field_path.to_string(),
+        }),
+        ..Default::default()
+    };
+
+    api::Volume {
+        name: exporter::PODINFO_VOLUME_NAME.to_string(),
+        downward_api: Some(api::DownwardAPIVolumeSource {
+            default_mode: Some(0o644),
+            items: Some(vec![
+                item("labels", "metadata.labels"),
+                item("annotations", "metadata.annotations"),
+            ]),
+        }),
+        ..Default::default()
+    }
+}
+
+/// build_configuration_volume creates the volume containing the
+/// configuration of an exporter for an instance with the passed role
+fn build_configuration_volume(exporter_config: &ExporterConfig, role: InstanceRole) -> api::Volume {
+    let config_file_name = exporter_config.backend.config_file_name();
+
+    api::Volume {
+        name: exporter_config.volume_name.clone(),
+        config_map: Some(api::ConfigMapVolumeSource {
             default_mode: Some(0o644),
             items: Some(vec![api::KeyToPath {
-                key: "config.yml".to_string(),
+                key: config_file_name.to_string(),
                 mode: None,
-                path: "config.yml".to_string(),
+                path: config_file_name.to_string(),
             }]),
-            name: Some(
-                helper
-                    .get_parameter(crate::consts::CONFIG_MAP_PARAMETER_NAME)
-                    .ok_or(Status::invalid_argument("Missing config map parameter"))?
-                    .to_string(),
-            ),
+            name: Some(exporter_config.config_map_name(role).to_string()),
             optional: Some(false),
-        });
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD_JSON: &str = r#"
+{
+    "apiVersion": "v1",
+    "kind": "Pod",
+    "metadata": {
+        "name": "cluster-example-1",
+        "namespace": "default"
+    },
+    "spec": {
+        "containers": [
+            {
+                "name": "postgres",
+                "image": "ghcr.io/cloudnative-pg/postgresql:16"
+            }
+        ],
+        "initContainers": [
+            {
+                "name": "bootstrap-controller",
+                "image": "ghcr.io/cloudnative-pg/cloudnative-pg:1.25.0"
+            }
+        ],
+        "volumes": [
+            {
+                "name": "scratch-data",
+                "emptyDir": {}
+            }
+        ]
+    }
+}"#;
+
+    fn pod() -> api::Pod {
+        serde_json::from_str(POD_JSON).unwrap()
+    }
+
+    #[test]
+    fn test_inject_single_exporter() {
+        let mut pod = pod();
+        inject_exporters(
+            &DataLoader::from_parameters(
+                serde_json::json!({"configMapName": "sql-exporter-config"}),
+            ),
+            &mut pod,
+        )
+        .unwrap();
 
-        // Inject the sidecar and the configuration volume
-        pod.spec
-            .as_mut()
-            .ok_or(Status::invalid_argument("CNPG Pod without spec?"))?
+        let spec = pod.spec.unwrap();
+        let sidecar = &spec.init_containers.unwrap()[1];
+        assert_eq!(sidecar.name, "sql-exporter");
+        assert_eq!(sidecar.restart_policy.as_deref(), Some("Always"));
+        assert_eq!(sidecar.ports.as_ref().unwrap()[0].container_port, 9237);
+
+        let volume = &spec.volumes.unwrap()[2];
+        assert_eq!(volume.name, "sql-exporter-configuration");
+        assert_eq!(
+            volume.config_map.as_ref().unwrap().name.as_deref(),
+            Some("sql-exporter-config")
+        );
+    }
+
+    #[test]
+    fn test_inject_multiple_exporters() {
+        let mut pod = pod();
+        inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({
+                "exporters": r#"[
+                    {"name": "business", "configMapName": "business", "port": 9300},
+                    {"name": "infra", "configMapName": "infra", "port": 9301}
+                ]"#
+            })),
+            &mut pod,
+        )
+        .unwrap();
+
+        let spec = pod.spec.unwrap();
+        let names: Vec<_> = spec
             .init_containers
-            .as_mut()
-            .ok_or(Status::invalid_argument("CNPG Pod without init containers?"))?
-            .push(generic_exporter_sidecar);
-        pod.spec
-            .as_mut()
-            .ok_or(Status::invalid_argument("CNPG Pod without spec?"))?
-            .volumes
-            .as_mut()
-            .ok_or(Status::invalid_argument("CNPG Pod without volumes?"))?
-            .push(exporter_configuration_volume);
+            .unwrap()
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+        assert_eq!(names, vec!["bootstrap-controller", "business", "infra"]);
+        assert_eq!(spec.volumes.unwrap().len(), 4);
+    }
 
-        // Create the json patch
-        let patch = json_patch::diff(
-            &serde_json::to_value(original_pod).map_err(|e| {
-                Status::internal(format!(
-                    "Error while serializing CNPG pod [original]: {}",
-                    e
-                ))
-            })?,
-            &serde_json::to_value(pod).map_err(|e| {
-                Status::internal(format!("Error while serializing CNPG pod [new]: {}", e))
-            })?,
+    fn injected_config_map(pod_name: &str) -> (String, String) {
+        let mut pod = pod();
+        pod.metadata.name = Some(pod_name.to_string());
+        inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({
+                "primaryConfigMapName": "primary-config",
+                "replicaConfigMapName": "replica-config"
+            })),
+            &mut pod,
+        )
+        .unwrap();
+
+        let role = pod.metadata.labels.unwrap()[crate::consts::CONFIG_ROLE_LABEL].clone();
+        let config_map = pod.spec.unwrap().volumes.unwrap()[2]
+            .config_map
+            .as_ref()
+            .unwrap()
+            .name
+            .clone()
+            .unwrap();
+        (role, config_map)
+    }
+
+    #[test]
+    fn test_inject_role_configuration() {
+        assert_eq!(
+            injected_config_map("cluster-example-1"),
+            ("primary".to_string(), "primary-config".to_string())
+        );
+        assert_eq!(
+            injected_config_map("cluster-example-2"),
+            ("replica".to_string(), "replica-config".to_string())
+        );
+    }
+
+    #[test]
+    fn test_inject_image_pull_secrets() {
+        let mut pod = pod();
+        pod.spec.as_mut().unwrap().image_pull_secrets = Some(vec![api::LocalObjectReference {
+            name: Some("registry".to_string()),
+        }]);
+        inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "imagePullPolicy": "IfNotPresent",
+                "imagePullSecrets": ["registry", "mirror"]
+            })),
+            &mut pod,
+        )
+        .unwrap();
+
+        let spec = pod.spec.unwrap();
+        let secrets: Vec<_> = spec
+            .image_pull_secrets
+            .unwrap()
+            .into_iter()
+            .map(|x| x.name.unwrap())
+            .collect();
+        assert_eq!(secrets, vec!["registry", "mirror"]);
+        assert_eq!(
+            spec.init_containers.unwrap()[1]
+                .image_pull_policy
+                .as_deref(),
+            Some("IfNotPresent")
         );
+    }
 
-        let serialized_patch = serde_json::to_string(&patch)
-            .map_err(|e| Status::internal(format!("While serializing patch: {}", e)))?;
+    #[test]
+    fn test_inject_shared_volumes() {
+        let mut pod = pod();
+        inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "volumes": [{"name": "ca", "secret": {"secretName": "foreign-ca"}}],
+                "volumeMounts": [{"name": "ca", "mountPath": "/etc/ssl/foreign"}],
+                "exporters": [
+                    {"name": "business", "port": 9300},
+                    {"name": "infra", "port": 9301}
+                ]
+            })),
+            &mut pod,
+        )
+        .unwrap();
 
-        debug!("Serialized patch: {}", serialized_patch);
+        let spec = pod.spec.unwrap();
+        let volumes = spec.volumes.unwrap();
+        assert_eq!(volumes.iter().filter(|x| x.name == "ca").count(), 1);
+        for sidecar in &spec.init_containers.unwrap()[1..] {
+            let mounts = sidecar.volume_mounts.as_ref().unwrap();
+            assert_eq!(mounts.last().unwrap().mount_path, "/etc/ssl/foreign");
+        }
+    }
 
-        return Ok(Response::new(cnpg::OperatorLifecycleResponse {
-            json_patch: serialized_patch.into_bytes(),
+    fn scratch_data_mounts(parameters: serde_json::Value) -> Vec<api::VolumeMount> {
+        let mut pod = pod();
+        inject_exporters(&DataLoader::from_parameters(parameters), &mut pod).unwrap();
+
+        pod.spec.unwrap().init_containers.unwrap()[1]
+            .volume_mounts
+            .clone()
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.name == "scratch-data")
+            .collect()
+    }
+
+    #[test]
+    fn test_inject_scratch_data_mounts() {
+        let mounts = scratch_data_mounts(serde_json::json!({"configMapName": "config"}));
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].mount_path, "/controller/run");
+        assert_eq!(mounts[0].sub_path.as_deref(), Some("run"));
+        assert_eq!(mounts[0].read_only, Some(true));
+
+        let mounts = scratch_data_mounts(
+            serde_json::json!({"configMapName": "config", "scratchDataMount": "full"}),
+        );
+        let paths: Vec<_> = mounts.iter().map(|x| x.mount_path.as_str()).collect();
+        assert_eq!(paths, vec!["/controller", "/run"]);
+    }
+
+    #[test]
+    fn test_inject_without_scratch_data() {
+        let mut pod = pod();
+        pod.spec.as_mut().unwrap().volumes = Some(vec![]);
+        let err = inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({"configMapName": "config"})),
+            &mut pod,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, Error::InvalidPod { .. }));
+    }
+
+    #[test]
+    fn test_inject_scrape_annotations() {
+        let loader = DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "sql-exporter-config",
+            "scrapeAnnotations": "true"
         }));
+
+        let mut annotated = pod();
+        inject_exporters(&loader, &mut annotated).unwrap();
+        let annotations = annotated.metadata.annotations.unwrap();
+        assert_eq!(annotations["prometheus.io/scrape"], "true");
+        assert_eq!(annotations["prometheus.io/port"], "9237");
+        assert_eq!(annotations["prometheus.io/path"], "/metrics");
+
+        let mut pod = pod();
+        pod.metadata.annotations = Some(BTreeMap::from([(
+            "prometheus.io/port".to_string(),
+            "9187".to_string(),
+        )]));
+        inject_exporters(&loader, &mut pod).unwrap();
+        assert_eq!(
+            pod.metadata.annotations.unwrap(),
+            BTreeMap::from([("prometheus.io/port".to_string(), "9187".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_inject_invalid_parameters() {
+        let mut pod = pod();
+        let err = inject_exporters(
+            &DataLoader::from_parameters(serde_json::json!({})),
+            &mut pod,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, Error::InvalidParameter { .. }));
     }
 }
@@ -0,0 +1,398 @@
+use crate::{
+    config::{self, ParameterError},
+    consts, helper,
+    helper::DataLoader,
+    metrics::METRICS,
+    state::PluginState,
+};
+use log::{error, info};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, LazyLock, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// POLICY is the organization policy applied to every cluster
+pub static POLICY: LazyLock<PolicyStore> = LazyLock::new(PolicyStore::from_env);
+
+/// RELOAD_INTERVAL is how often the policy file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Policy contains the defaults and the constraints defined by the
+/// platform team for the plugin parameters of every cluster
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Policy {
+    /// defaults are the values of the plugin parameters used when a
+    /// cluster doesn't specify them
+    #[serde(default)]
+    pub defaults: BTreeMap<String, serde_json::Value>,
+
+    /// allowed_registries are the registries, or repository prefixes, the
+    /// exporter images can be pulled from. Every image is allowed when empty
+    #[serde(default)]
+    pub allowed_registries: Vec<String>,
+
+    /// require_resource_limits rejects the exporters without resource limits
+    #[serde(default)]
+    pub require_resource_limits: bool,
+
+    /// require_security_context rejects the exporters without a security context
+    #[serde(default)]
+    pub require_security_context: bool,
+
+    /// minimum_resources are the smallest resource requests allowed for
+    /// the exporters, keyed by resource name, e.g. `{cpu: 10m, memory: 32Mi}`
+    #[serde(default)]
+    pub minimum_resources: BTreeMap<String, serde_json::Value>,
+}
+
+impl Policy {
+    /// parse decodes a policy file, checking that the defaults refer
+    /// to existing parameters
+    pub fn parse(content: &str) -> Result<Policy, String> {
+        let policy: Policy = serde_yaml::from_str(content).map_err(|err| err.to_string())?;
+        if let Some(name) = policy
+            .defaults
+            .keys()
+            .find(|x| !consts::SUPPORTED_PARAMETERS.contains(&x.as_str()))
+        {
+            return Err(format!("defaults: unknown parameter {}", name));
+        }
+        for (name, value) in &policy.defaults {
+            helper::coerce_parameter_value(value)
+                .map_err(|message| format!("defaults: {}: {}", name, message))?;
+        }
+        for (name, value) in &policy.minimum_resources {
+            helper::coerce_parameter_value(value)
+                .and_then(|value| parse_quantity(&value))
+                .map_err(|message| format!("minimumResources: {}: {}", name, message))?;
+        }
+        Ok(policy)
+    }
+
+    /// default_parameters are the default values of the parameters,
+    /// coerced like the ones of the clusters
+    pub fn default_parameters(&self) -> HashMap<String, String> {
+        self.defaults
+            .iter()
+            .filter_map(|(name, value)| {
+                helper::coerce_parameter_value(value)
+                    .ok()
+                    .map(|value| (name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// check finds the exporters of a cluster violating the constraints
+    /// of the policy. Invalid parameters are reported by load_exporters
+    pub fn check(&self, loader: &DataLoader) -> Vec<ParameterError> {
+        let Ok(exporters) = config::load_exporters(loader) else {
+            return Vec::new();
+        };
+
+        let mut result = Vec::new();
+        for exporter in &exporters {
+            if !self.allows_image(&exporter.image) {
+                result.push(config::exporter_error(
+                    loader,
+                    exporter,
+                    consts::IMAGE_NAME_PARAMETER_NAME,
+                    format!(
+                        "{} is not allowed by the policy, allowed registries are: {}",
+                        exporter.image,
+                        self.allowed_registries.join(", ")
+                    ),
+                ));
+            }
+
+            let has_limits = exporter
+                .resources
+                .as_ref()
+                .and_then(|x| x.limits.as_ref())
+                .is_some_and(|x| !x.is_empty());
+            if self.require_resource_limits && !has_limits {
+                result.push(config::exporter_error(
+                    loader,
+                    exporter,
+                    consts::RESOURCES_PARAMETER_NAME,
+                    "resource limits are required by the policy".to_string(),
+                ));
+            }
+
+            for (name, minimum) in self.minimum_resources() {
+                // Kubernetes uses the limit as request when only the limit is set
+                let requested = exporter.resources.as_ref().and_then(|x| {
+                    x.requests
+                        .as_ref()
+                        .and_then(|requests| requests.get(&name))
+                        .or_else(|| x.limits.as_ref().and_then(|limits| limits.get(&name)))
+                });
+                if requested.is_none_or(|x| parse_quantity(&x.0).is_ok_and(|x| x < minimum.1)) {
+                    result.push(config::exporter_error(
+                        loader,
+                        exporter,
+                        consts::RESOURCES_PARAMETER_NAME,
+                        format!(
+                            "a {} request of at least {} is required by the policy",
+                            name, minimum.0
+                        ),
+                    ));
+                }
+            }
+
+            if self.require_security_context && exporter.security_context.is_none() {
+                result.push(config::exporter_error(
+                    loader,
+                    exporter,
+                    consts::SECURITY_CONTEXT_PARAMETER_NAME,
+                    "a security context is required by the policy".to_string(),
+                ));
+            }
+        }
+
+        result
+    }
+
+    /// minimum_resources are the minimum resource requests, with both
+    /// the quantity and its value. Invalid values are rejected by parse
+    fn minimum_resources(&self) -> Vec<(String, (String, f64))> {
+        self.minimum_resources
+            .iter()
+            .filter_map(|(name, value)| {
+                let quantity = helper::coerce_parameter_value(value).ok()?;
+                let parsed = parse_quantity(&quantity).ok()?;
+                Some((name.clone(), (quantity, parsed)))
+            })
+            .collect()
+    }
+
+    /// allows_image tells if an image is pulled from an allowed registry
+    fn allows_image(&self, image: &str) -> bool {
+        self.allowed_registries.is_empty()
+            || self.allowed_registries.iter().any(|registry| {
+                image
+                    .strip_prefix(registry.trim_end_matches('/'))
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+}
+
+/// parse_quantity decodes a Kubernetes resource quantity, like `100m`,
+/// `64Mi` or `1e3`, to its value
+fn parse_quantity(quantity: &str) -> Result<f64, String> {
+    const SUFFIXES: &[(&str, f64)] = &[
+        ("Ki", 1024.0),
+        ("Mi", 1048576.0),
+        ("Gi", 1073741824.0),
+        ("Ti", 1099511627776.0),
+        ("Pi", 1125899906842624.0),
+        ("Ei", 1152921504606846976.0),
+        ("n", 1e-9),
+        ("u", 1e-6),
+        ("m", 1e-3),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+
+    let invalid = || format!("{} is not a valid quantity", quantity);
+    let (number, multiplier) = SUFFIXES
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            quantity
+                .strip_suffix(suffix)
+                .map(|number| (number, *multiplier))
+        })
+        .unwrap_or((quantity, 1.0));
+    if number.is_empty() || number.starts_with('-') || number.contains(['i', 'n', 'N']) {
+        return Err(invalid());
+    }
+    number
+        .parse::<f64>()
+        .map(|value| value * multiplier)
+        .map_err(|_| invalid())
+}
+
+/// PolicyStore holds the policy loaded from the file referenced by the
+/// POLICY_FILE environment variable, reloading it when it changes
+pub struct PolicyStore {
+    path: Option<String>,
+    current: RwLock<Arc<Policy>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PolicyStore {
+    /// from_env is the store of the policy file referenced by the
+    /// POLICY_FILE environment variable. Without it, the policy is empty
+    fn from_env() -> PolicyStore {
+        PolicyStore {
+            path: std::env::var(consts::POLICY_FILE_ENV).ok(),
+            current: RwLock::new(Arc::new(Policy::default())),
+            modified: Mutex::new(None),
+        }
+    }
+
+    /// get is the current policy
+    pub fn get(&self) -> Arc<Policy> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// load reads the policy file. When the policy file is not valid the
+    /// previous policy is kept, and the error is returned
+    pub fn load(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        *self.modified.lock().unwrap() = std::fs::metadata(path).and_then(|x| x.modified()).ok();
+        let policy = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|content| Policy::parse(&content))
+            .map_err(|err| format!("error while loading the policy from {}: {}", path, err))?;
+        info!("Loaded the policy from {}", path);
+        *self.current.write().unwrap() = Arc::new(policy);
+        Ok(())
+    }
+
+    /// reload loads the policy file when it has been changed since it was
+    /// last read, returning the outcome
+    fn reload(&self) -> Option<Result<(), String>> {
+        let path = self.path.as_ref()?;
+        let modified = std::fs::metadata(path).and_then(|x| x.modified()).ok();
+        if modified.is_some() && modified == *self.modified.lock().unwrap() {
+            return None;
+        }
+        Some(self.load())
+    }
+
+    /// watch reloads the policy file when it changes. Kubernetes updates
+    /// the files mounted from a ConfigMap by replacing them, so their
+    /// modification time is checked periodically. When the file becomes
+    /// invalid the last valid policy is kept, and the error is reported in
+    /// the logs and in the metrics. A valid file makes the plugin ready
+    /// when the policy couldn't be loaded at startup
+    pub async fn watch(&self, state: Arc<PluginState>) {
+        if self.path.is_none() {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.reload() {
+                None => {}
+                Some(Ok(())) => {
+                    METRICS.set_policy_reload_failed(false);
+                    state.set_configuration_status("policy", Ok(()));
+                }
+                Some(Err(err)) => {
+                    error!("{}, keeping the previous policy", err);
+                    METRICS.set_policy_reload_failed(true);
+                    METRICS.inc_policy_reload_errors();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY_YAML: &str = r#"
+defaults:
+  imageName: registry.example.com/sql_exporter:0.5
+  resources:
+    limits:
+      memory: 64Mi
+allowedRegistries:
+- registry.example.com/
+requireSecurityContext: true
+"#;
+
+    #[test]
+    fn test_policy_defaults() {
+        let parameters = Policy::parse(POLICY_YAML).unwrap().default_parameters();
+
+        assert_eq!(
+            parameters["imageName"],
+            "registry.example.com/sql_exporter:0.5"
+        );
+        assert_eq!(parameters["resources"], r#"{"limits":{"memory":"64Mi"}}"#);
+
+        assert!(Policy::parse("defaults: {unknown: x}").is_err());
+        assert!(Policy::parse("defaults: {port: null}").is_err());
+        assert!(Policy::parse("requireEverything: true").is_err());
+        assert!(Policy::parse("minimumResources: {cpu: lots}").is_err());
+    }
+
+    #[test]
+    fn test_policy_check() {
+        let policy = Policy::parse(POLICY_YAML).unwrap();
+
+        let errors = policy.check(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imageName": "registry.example.com.evil.io/sql_exporter:0.5"
+        })));
+        assert_eq!(
+            errors
+                .iter()
+                .map(|x| x.parameter.as_str())
+                .collect::<Vec<_>>(),
+            vec!["imageName", "securityContext"]
+        );
+
+        let errors = policy.check(&DataLoader::from_parameters(serde_json::json!({
+            "configMapName": "config",
+            "imageName": "registry.example.com/sql_exporter:0.5",
+            "securityContext": "{runAsNonRoot: true}"
+        })));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_policy_minimum_resources() {
+        let policy = Policy::parse("minimumResources: {cpu: 10m, memory: 32Mi}").unwrap();
+        let check = |resources: &str| {
+            let loader = DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "resources": resources
+            }));
+            assert!(config::load_exporters(&loader).is_ok());
+            policy
+                .check(&loader)
+                .into_iter()
+                .map(|x| x.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(check("requests: {cpu: 50m, memory: 0.5Gi}").is_empty());
+        assert!(check("limits: {cpu: 1, memory: 64Mi}").is_empty());
+        assert_eq!(
+            check("{requests: {cpu: 5m}, limits: {memory: 16Mi}}"),
+            vec![
+                "a cpu request of at least 10m is required by the policy",
+                "a memory request of at least 32Mi is required by the policy"
+            ]
+        );
+        assert_eq!(check("{}").len(), 2);
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("100m"), Ok(0.1));
+        assert_eq!(parse_quantity("64Mi"), Ok(67108864.0));
+        assert_eq!(parse_quantity("1.5"), Ok(1.5));
+        assert_eq!(parse_quantity("2k"), Ok(2000.0));
+        assert_eq!(parse_quantity("1e3"), Ok(1000.0));
+        assert!(parse_quantity("").is_err());
+        assert!(parse_quantity("-1").is_err());
+        assert!(parse_quantity("inf").is_err());
+        assert!(parse_quantity("Mi").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    config::{MonitoringSettings, ParameterError},
+    consts,
+    helper::DataLoader,
+};
+
+/// SHARED_PRELOAD_LIBRARIES is the field of the PostgreSQL configuration
+/// of the Cluster containing the libraries loaded at server start
+const SHARED_PRELOAD_LIBRARIES: &str = "shared_preload_libraries";
+
+/// PG_STAT_STATEMENTS is the library collecting the statement statistics
+const PG_STAT_STATEMENTS: &str = "pg_stat_statements";
+
+/// TRACK_IO_TIMING is the PostgreSQL setting enabling the timing of I/O calls
+const TRACK_IO_TIMING: &str = "track_io_timing";
+
+/// TRACK_IO_TIMING_POINTER is where track_io_timing is set in a Cluster
+const TRACK_IO_TIMING_POINTER: &str = "/spec/postgresql/parameters/track_io_timing";
+
+/// enrich adds the PostgreSQL settings required by the monitoring settings
+/// to the `.spec.postgresql` section of a Cluster definition, merging them
+/// with the existing configuration. The values set in the Cluster are never
+/// overwritten, and sections not containing an object are left alone
+pub fn enrich(settings: &MonitoringSettings, cluster: &mut serde_json::Value) {
+    if settings.stat_statements {
+        let libraries = cluster.pointer("/spec/postgresql/shared_preload_libraries");
+        if libraries.is_none_or(|x| x.is_null()) {
+            if let Some(postgresql) = section(cluster, &["spec", "postgresql"]) {
+                postgresql.insert(
+                    SHARED_PRELOAD_LIBRARIES.to_string(),
+                    serde_json::json!([PG_STAT_STATEMENTS]),
+                );
+            }
+        } else if let Some(libraries) = cluster
+            .pointer_mut("/spec/postgresql/shared_preload_libraries")
+            .and_then(|x| x.as_array_mut())
+        {
+            if !libraries.iter().any(|x| x == PG_STAT_STATEMENTS) {
+                libraries.push(PG_STAT_STATEMENTS.into());
+            }
+        }
+    }
+
+    if settings.track_io_timing
+        && cluster
+            .pointer(TRACK_IO_TIMING_POINTER)
+            .is_none_or(|x| x.is_null())
+    {
+        if let Some(parameters) = section(cluster, &["spec", "postgresql", "parameters"]) {
+            parameters.insert(TRACK_IO_TIMING.to_string(), "on".into());
+        }
+    }
+}
+
+/// section is the object at the passed path of a Cluster definition, which
+/// is created when missing. There is no section when a value along the path
+/// is not an object
+fn section<'a>(
+    cluster: &'a mut serde_json::Value,
+    path: &[&str],
+) -> Option<&'a mut serde_json::Map<String, serde_json::Value>> {
+    let mut current = cluster.as_object_mut()?;
+    for name in path {
+        let value = current
+            .entry(name.to_string())
+            .or_insert(serde_json::Value::Null);
+        if value.is_null() {
+            *value = serde_json::Value::Object(Default::default());
+        }
+        current = value.as_object_mut()?;
+    }
+    Some(current)
+}
+
+/// warnings are the monitoring settings which are not applied, since the
+/// Cluster sets a different value for them
+pub fn warnings(loader: &DataLoader) -> Vec<ParameterError> {
+    let track_io_timing = loader
+        .get_bool_parameter(consts::ENABLE_TRACK_IO_TIMING_PARAMETER_NAME)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    match loader.cluster_field(TRACK_IO_TIMING_POINTER) {
+        Some(value) if track_io_timing && !is_enabled(value) => vec![ParameterError {
+            parameter: consts::ENABLE_TRACK_IO_TIMING_PARAMETER_NAME.to_string(),
+            message: format!(
+                "{} is set to {} in .spec.postgresql.parameters, which is kept",
+                TRACK_IO_TIMING, value
+            ),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// is_enabled tells if the value of a boolean PostgreSQL setting is true
+fn is_enabled(value: &str) -> bool {
+    matches!(
+        value.to_ascii_lowercase().as_str(),
+        "on" | "true" | "yes" | "1"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enrich_merges_libraries() {
+        let settings = MonitoringSettings {
+            stat_statements: true,
+            track_io_timing: true,
+            ..Default::default()
+        };
+        let mut cluster = serde_json::json!({
+            "spec": {
+                "postgresql": {
+                    "shared_preload_libraries": ["auto_explain", "pg_failover_slots"],
+                    "parameters": {"work_mem": "8MB"}
+                }
+            }
+        });
+
+        enrich(&settings, &mut cluster);
+        assert_eq!(
+            cluster,
+            serde_json::json!({
+                "spec": {
+                    "postgresql": {
+                        "shared_preload_libraries": [
+                            "auto_explain",
+                            "pg_failover_slots",
+                            "pg_stat_statements"
+                        ],
+                        "parameters": {"work_mem": "8MB", "track_io_timing": "on"}
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_enrich_without_changes() {
+        let settings = MonitoringSettings {
+            stat_statements: true,
+            track_io_timing: false,
+            ..Default::default()
+        };
+        let cluster = serde_json::json!({
+            "spec": {"postgresql": {"shared_preload_libraries": ["pg_stat_statements"]}}
+        });
+
+        let mut enriched = cluster.clone();
+        enrich(&settings, &mut enriched);
+        assert_eq!(enriched, cluster);
+
+        let cluster = serde_json::json!({"spec": {"instances": 3}});
+        let mut enriched = cluster.clone();
+        enrich(&MonitoringSettings::default(), &mut enriched);
+        assert_eq!(enriched, cluster);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let load = |track_io_timing: &str| {
+            let cluster = serde_json::json!({
+                "apiVersion": "postgresql.cnpg.io/v1",
+                "kind": "Cluster",
+                "metadata": {"name": "cluster-example", "namespace": "default"},
+                "spec": {
+                    "postgresql": {"parameters": {"track_io_timing": track_io_timing}},
+                    "plugins": [{
+                        "name": consts::PLUGIN_NAME,
+                        "parameters": {"configMapName": "config", "enableTrackIoTiming": "true"}
+                    }]
+                }
+            });
+            DataLoader::from_cluster(consts::PLUGIN_NAME, cluster.to_string().as_bytes()).unwrap()
+        };
+
+        let warnings = warnings(&load("off"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].parameter, "enableTrackIoTiming");
+        assert_eq!(
+            warnings[0].message,
+            "track_io_timing is set to off in .spec.postgresql.parameters, which is kept"
+        );
+
+        assert!(super::warnings(&load("ON")).is_empty());
+        assert!(
+            super::warnings(&DataLoader::from_parameters(serde_json::json!({
+                "configMapName": "config",
+                "enableTrackIoTiming": "true"
+            })))
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_enrich_keeps_cluster_values() {
+        let settings = MonitoringSettings {
+            stat_statements: true,
+            track_io_timing: true,
+            ..Default::default()
+        };
+        let cluster = serde_json::json!({
+            "spec": {
+                "postgresql": {
+                    "shared_preload_libraries": "pg_stat_statements",
+                    "parameters": {"track_io_timing": "off"}
+                }
+            }
+        });
+        let mut enriched = cluster.clone();
+        enrich(&settings, &mut enriched);
+        assert_eq!(enriched, cluster);
+
+        for cluster in [
+            serde_json::json!({"spec": "invalid"}),
+            serde_json::json!({"spec": {"postgresql": ["invalid"]}}),
+            serde_json::json!({"spec": {"postgresql": {"parameters": "invalid"}}}),
+        ] {
+            let mut enriched = cluster.clone();
+            enrich(&settings, &mut enriched);
+            assert!(enriched.pointer(TRACK_IO_TIMING_POINTER).is_none());
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    capabilities::{Registry, Service},
+    cnpg, config, database,
+    helper::DataLoader,
+    network_policy, service,
+    state::PluginState,
+};
+use log::{error, warn};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+/// register adds the reconciler hooks service to the registry
+pub fn register(registry: &mut Registry) {
+    registry.register(Service::Reconciler);
+}
+
+pub struct ReconcilerImpl {
+    state: Arc<PluginState>,
+}
+
+impl ReconcilerImpl {
+    pub fn new(state: Arc<PluginState>) -> Self {
+        ReconcilerImpl { state }
+    }
+}
+
+/// proceed is the result letting CNPG continue its reconciliation loop
+fn proceed() -> Response<cnpg::ReconcilerHooksResult> {
+    Response::new(cnpg::ReconcilerHooksResult {
+        behavior: cnpg::reconciler_hooks_result::Behavior::Continue.into(),
+        requeue_after: 0,
+    })
+}
+
+#[tonic::async_trait]
+impl cnpg::reconciler_hooks_server::ReconcilerHooks for ReconcilerImpl {
+    /// GetCapabilities gets the capabilities of the ReconcilerHooks service
+    async fn get_capabilities(
+        &self,
+        _request: Request<cnpg::ReconcilerHooksCapabilitiesRequest>,
+    ) -> Result<Response<cnpg::ReconcilerHooksCapabilitiesResult>, Status> {
+        Ok(Response::new(cnpg::ReconcilerHooksCapabilitiesResult {
+            reconciler_capabilities: vec![cnpg::ReconcilerHooksCapability {
+                kind: cnpg::reconciler_hooks_capability::Kind::Cluster.into(),
+            }],
+        }))
+    }
+
+    /// Pre is executed before CNPG reconciles the cluster
+    async fn pre(
+        &self,
+        _request: Request<cnpg::ReconcilerHooksRequest>,
+    ) -> Result<Response<cnpg::ReconcilerHooksResult>, Status> {
+        Ok(proceed())
+    }
+
+    /// Post is executed after CNPG reconciled the cluster, and ensures
+    /// the monitoring extensions exist in the configured databases and
+    /// the exporters are exposed and reachable as requested
+    async fn post(
+        &self,
+        request: Request<cnpg::ReconcilerHooksRequest>,
+    ) -> Result<Response<cnpg::ReconcilerHooksResult>, Status> {
+        let loader = DataLoader::from_cluster(
+            crate::consts::PLUGIN_NAME,
+            &request.get_ref().cluster_definition,
+        )?;
+
+        let Some(client) = self.state.kubernetes_client() else {
+            warn!(
+                "Kubernetes API not available, cannot reconcile the resources of {}",
+                loader.cluster_name()
+            );
+            return Ok(proceed());
+        };
+
+        // Invalid parameters are reported by the validation webhook, and
+        // failures are reported in the plugin status: neither of them
+        // should block the reconciliation of the cluster
+        let mut errors = Vec::new();
+        if let Ok(settings) = config::load_monitoring_settings(&loader) {
+            if let Err(database_errors) = database::reconcile(client, &loader, &settings).await {
+                errors.extend(
+                    database_errors
+                        .iter()
+                        .map(|err| format!("cannot reconcile the monitoring databases: {}", err)),
+                );
+            }
+        }
+
+        if let (Ok(settings), Ok(exporters)) = (
+            config::load_scrape_settings(&loader),
+            config::load_exporters(&loader),
+        ) {
+            if let Err(err) = service::reconcile(client, &loader, &settings, &exporters).await {
+                errors.push(format!("cannot reconcile the exporter service: {}", err));
+            }
+
+            let policy = settings.network_policy.as_ref();
+            if let Err(err) = network_policy::reconcile(client, &loader, policy, &exporters).await {
+                errors.push(format!(
+                    "cannot reconcile the exporter network policy: {}",
+                    err
+                ));
+            }
+        }
+
+        for err in &errors {
+            error!("Error while reconciling {}: {}", loader.cluster_name(), err);
+        }
+        self.state
+            .set_reconcile_errors(loader.cluster_name(), errors);
+
+        Ok(proceed())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{consts, helper::DataLoader};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{
+    api::{Api, ListParams, Patch, PatchParams},
+    Resource,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::BTreeMap, fmt::Debug};
+use thiserror::Error;
+
+/// ClusterRef identifies the Cluster owning the resources created
+/// by the plugin
+pub struct ClusterRef<'a> {
+    pub namespace: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> ClusterRef<'a> {
+    /// from_loader identifies the cluster being processed by a helper
+    pub fn from_loader(loader: &'a DataLoader) -> Self {
+        ClusterRef {
+            namespace: loader
+                .cluster_field("/metadata/namespace")
+                .unwrap_or_default(),
+            name: loader.cluster_field("/metadata/name").unwrap_or_default(),
+        }
+    }
+
+    /// labels are the labels of the resources created for this cluster
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([(consts::CLUSTER_LABEL.to_string(), self.name.to_string())])
+    }
+
+    /// selector selects the resources created for this cluster
+    pub fn selector(&self) -> ListParams {
+        ListParams::default().labels(&format!("{}={}", consts::CLUSTER_LABEL, self.name))
+    }
+
+    /// is_owner tells if a resource has been created for this cluster
+    pub fn is_owner(&self, labels: Option<&BTreeMap<String, String>>) -> bool {
+        labels
+            .and_then(|x| x.get(consts::CLUSTER_LABEL))
+            .map(|x| x.as_str())
+            == Some(self.name)
+    }
+}
+
+/// owner_references makes the resources created for a cluster be
+/// garbage collected with it. The cluster UID is not known in tests
+pub fn owner_references(loader: &DataLoader) -> Option<Vec<OwnerReference>> {
+    let uid = loader.cluster_field("/metadata/uid")?;
+    Some(vec![OwnerReference {
+        api_version: "postgresql.cnpg.io/v1".to_string(),
+        kind: "Cluster".to_string(),
+        name: ClusterRef::from_loader(loader).name.to_string(),
+        uid: uid.to_string(),
+        ..Default::default()
+    }])
+}
+
+/// ResourceError is an error while managing the resources created
+/// for a cluster
+#[derive(Error, Debug)]
+pub enum ResourceError {
+    #[error(transparent)]
+    Kubernetes(#[from] kube::Error),
+
+    #[error(
+        "{kind} {name} already exists and has not been created by the plugin for this cluster"
+    )]
+    Conflict { kind: String, name: String },
+
+    #[error(
+        "the {database} database is already managed by Database {name}, which has not been created by the plugin for this cluster"
+    )]
+    DatabaseManaged { database: String, name: String },
+}
+
+/// apply creates or updates a resource owned by the plugin via server-side
+/// apply. An existing resource with the same name which has not been
+/// created for the cluster is never taken over
+pub async fn apply<K>(
+    api: &Api<K>,
+    cluster: &ClusterRef<'_>,
+    kind: &str,
+    name: &str,
+    resource: &(impl Serialize + Debug),
+) -> Result<(), ResourceError>
+where
+    K: Resource + Clone + DeserializeOwned + Debug,
+{
+    if let Some(existing) = api.get_opt(name).await? {
+        if !cluster.is_owner(existing.meta().labels.as_ref()) {
+            return Err(ResourceError::Conflict {
+                kind: kind.to_string(),
+                name: name.to_string(),
+            });
+        }
+    }
+
+    let params = PatchParams::apply(consts::PLUGIN_NAME).force();
+    api.patch(name, &params, &Patch::Apply(resource)).await?;
+    Ok(())
+}
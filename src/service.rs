@@ -0,0 +1,133 @@
+use crate::{
+    config::{ExporterConfig, ScrapeSettings},
+    helper::DataLoader,
+    resources::{self, ClusterRef, ResourceError},
+};
+use k8s_openapi::{
+    api::core::v1 as api,
+    apimachinery::pkg::{apis::meta::v1::ObjectMeta, util::intstr::IntOrString},
+};
+use kube::api::{Api, DeleteParams};
+use std::collections::BTreeMap;
+
+/// service_name is the name of the headless Service exposing the
+/// exporters of a cluster
+fn service_name(cluster: &str) -> String {
+    format!("{}-exporter", cluster)
+}
+
+/// build_service creates the headless Service selecting the instances of
+/// a cluster, annotated to be discovered by Prometheus. The annotations
+/// can only describe one port, so they refer to the first exporter
+fn build_service(loader: &DataLoader, exporters: &[ExporterConfig]) -> api::Service {
+    let cluster = ClusterRef::from_loader(loader);
+
+    api::Service {
+        metadata: ObjectMeta {
+            name: Some(service_name(cluster.name)),
+            namespace: Some(cluster.namespace.to_string()),
+            labels: Some(cluster.labels()),
+            annotations: exporters.first().map(|x| x.scrape_annotations()),
+            owner_references: resources::owner_references(loader),
+            ..Default::default()
+        },
+        spec: Some(api::ServiceSpec {
+            cluster_ip: Some("None".to_string()),
+            selector: Some(BTreeMap::from([
+                ("cnpg.io/cluster".to_string(), cluster.name.to_string()),
+                ("cnpg.io/podRole".to_string(), "instance".to_string()),
+            ])),
+            ports: Some(
+                exporters
+                    .iter()
+                    .map(|exporter| api::ServicePort {
+                        name: Some(exporter.port_name.clone()),
+                        port: exporter.port.into(),
+                        target_port: Some(IntOrString::String(exporter.port_name.clone())),
+                        protocol: Some("TCP".to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// reconcile creates or updates the headless Service exposing the
+/// exporters, removing it when it is no longer requested
+pub async fn reconcile(
+    client: &kube::Client,
+    loader: &DataLoader,
+    settings: &ScrapeSettings,
+    exporters: &[ExporterConfig],
+) -> Result<(), ResourceError> {
+    let cluster = ClusterRef::from_loader(loader);
+    if !settings.service {
+        return Ok(remove(client, &cluster).await?);
+    }
+
+    let service = build_service(loader, exporters);
+    let api: Api<api::Service> = Api::namespaced(client.clone(), cluster.namespace);
+    resources::apply(
+        &api,
+        &cluster,
+        "Service",
+        &service_name(cluster.name),
+        &service,
+    )
+    .await
+}
+
+/// remove deletes the headless Service of a cluster. A Service with
+/// the same name not created by the plugin is left untouched
+pub async fn remove(client: &kube::Client, cluster: &ClusterRef<'_>) -> Result<(), kube::Error> {
+    let api: Api<api::Service> = Api::namespaced(client.clone(), cluster.namespace);
+    let name = service_name(cluster.name);
+    match api.get_opt(&name).await? {
+        Some(service) if cluster.is_owner(service.metadata.labels.as_ref()) => {
+            api.delete(&name, &DeleteParams::default()).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_build_service() {
+        let loader = DataLoader::from_parameters(serde_json::json!({
+            "exporters": r#"[
+                {"name": "sql", "configMapName": "sql-config"},
+                {"name": "pg", "exporterType": "postgres-exporter", "configMapName": "pg-config"}
+            ]"#
+        }));
+        let exporters = config::load_exporters(&loader).unwrap();
+        let service = build_service(&loader, &exporters);
+
+        assert_eq!(
+            service.metadata.name.as_deref(),
+            Some("cluster-example-exporter")
+        );
+        assert_eq!(
+            service.metadata.annotations.unwrap()["prometheus.io/port"],
+            exporters[0].port.to_string()
+        );
+
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(spec.selector.unwrap()["cnpg.io/cluster"], "cluster-example");
+        let ports = spec.ports.unwrap();
+        assert_eq!(ports.len(), 2);
+        assert_eq!(
+            ports[1].target_port,
+            Some(IntOrString::String(exporters[1].port_name.clone()))
+        );
+    }
+}
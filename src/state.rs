@@ -0,0 +1,296 @@
+use crate::metrics::METRICS;
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+/// KUBERNETES_API_CHECK_TTL is the time for which the result of the
+/// Kubernetes API connectivity check is cached
+const KUBERNETES_API_CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// HEALTH_CHECK_INTERVAL is how often the status of the gRPC health
+/// service is updated from the readiness of the plugin
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// KUBERNETES_API_CHECK_TIMEOUT is the maximum time we wait for the
+/// Kubernetes API server to answer the connectivity check
+const KUBERNETES_API_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// KUBERNETES_API_HEALTH_SERVICE is the name used in the gRPC health service
+/// to report the connectivity with the Kubernetes API server
+pub const KUBERNETES_API_HEALTH_SERVICE: &str = "kubernetes-api";
+
+/// NotReadyReason explains why the plugin is not ready to receive requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotReadyReason {
+    ServerNotInitialized,
+    ConfigurationNotLoaded,
+    ConfigurationInvalid(String),
+}
+
+impl NotReadyReason {
+    /// metric_label is the value used for the `reason` label of the
+    /// plugin metrics
+    pub fn metric_label(&self) -> &'static str {
+        match self {
+            NotReadyReason::ServerNotInitialized => "server_not_initialized",
+            NotReadyReason::ConfigurationNotLoaded => "configuration_not_loaded",
+            NotReadyReason::ConfigurationInvalid(_) => "configuration_invalid",
+        }
+    }
+}
+
+impl fmt::Display for NotReadyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotReadyReason::ServerNotInitialized => write!(f, "gRPC server not initialized"),
+            NotReadyReason::ConfigurationNotLoaded => write!(f, "configuration not loaded"),
+            NotReadyReason::ConfigurationInvalid(err) => {
+                write!(f, "invalid configuration: {}", err)
+            }
+        }
+    }
+}
+
+/// PluginState tracks the internal state of the plugin, and is used
+/// to compute its readiness
+#[derive(Default)]
+pub struct PluginState {
+    server_initialized: AtomicBool,
+    configuration_loaded: AtomicBool,
+    configuration_errors: std::sync::Mutex<BTreeMap<&'static str, String>>,
+    reconcile_errors: std::sync::Mutex<HashMap<String, Vec<String>>>,
+    kubernetes_client: Option<kube::Client>,
+    kubernetes_check: Mutex<Option<(Instant, Result<(), String>)>>,
+}
+
+impl PluginState {
+    /// new creates a new plugin state. When a Kubernetes client is passed,
+    /// the connectivity to the API server is checked during the readiness
+    /// probe, reporting the plugin as degraded while it can't be reached
+    pub fn new(kubernetes_client: Option<kube::Client>) -> Self {
+        PluginState {
+            kubernetes_client,
+            ..Default::default()
+        }
+    }
+
+    /// kubernetes_client is the client of the Kubernetes API, when available
+    pub fn kubernetes_client(&self) -> Option<&kube::Client> {
+        self.kubernetes_client.as_ref()
+    }
+
+    /// mark_server_initialized is called when the listener of the gRPC
+    /// server has been bound
+    pub fn mark_server_initialized(&self) {
+        self.server_initialized.store(true, Ordering::SeqCst);
+    }
+
+    /// mark_configuration_loaded is called when the plugin configuration
+    /// has been loaded, with the outcome of every configuration source
+    pub fn mark_configuration_loaded(&self, sources: &[(&'static str, Result<(), String>)]) {
+        for (source, result) in sources {
+            self.set_configuration_status(source, result.clone());
+        }
+        self.configuration_loaded.store(true, Ordering::SeqCst);
+    }
+
+    /// set_configuration_status records the outcome of the last load of a
+    /// configuration source. The plugin is not ready while a configuration
+    /// source is invalid
+    pub fn set_configuration_status(&self, source: &'static str, result: Result<(), String>) {
+        let mut errors = self.configuration_errors.lock().unwrap();
+        match result {
+            Ok(()) => errors.remove(source),
+            Err(err) => errors.insert(source, err),
+        };
+    }
+
+    /// set_reconcile_errors records the errors of the last reconciliation
+    /// of the resources created for a cluster
+    pub fn set_reconcile_errors(&self, cluster: &str, errors: Vec<String>) {
+        let mut reconcile_errors = self.reconcile_errors.lock().unwrap();
+        if errors.is_empty() {
+            reconcile_errors.remove(cluster);
+        } else {
+            reconcile_errors.insert(cluster.to_string(), errors);
+        }
+    }
+
+    /// reconcile_errors are the errors of the last reconciliation of the
+    /// resources created for a cluster, to be reported in its status
+    pub fn reconcile_errors(&self, cluster: &str) -> Vec<String> {
+        self.reconcile_errors
+            .lock()
+            .unwrap()
+            .get(cluster)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// check_readiness computes the readiness of the plugin, reporting
+    /// the reason why it is not ready via the logs and the metrics. The
+    /// Kubernetes API is only needed by some of the services, so the plugin
+    /// is just reported as degraded while the API server can't be reached
+    pub async fn check_readiness(&self) -> Result<(), NotReadyReason> {
+        let result = self.compute_readiness().await;
+
+        METRICS.set_ready(result.is_ok());
+        if let Err(reason) = &result {
+            warn!("Plugin not ready: {}", reason);
+            METRICS.inc_not_ready(reason.metric_label());
+        }
+
+        let kubernetes_api = self.check_kubernetes_api().await;
+        METRICS.set_kubernetes_api_unreachable(kubernetes_api.is_err());
+        if let Err(err) = &kubernetes_api {
+            warn!("Plugin degraded, Kubernetes API unreachable: {}", err);
+        }
+
+        result
+    }
+
+    /// compute_readiness computes the readiness of the plugin
+    pub async fn compute_readiness(&self) -> Result<(), NotReadyReason> {
+        if !self.server_initialized.load(Ordering::SeqCst) {
+            return Err(NotReadyReason::ServerNotInitialized);
+        }
+
+        if !self.configuration_loaded.load(Ordering::SeqCst) {
+            return Err(NotReadyReason::ConfigurationNotLoaded);
+        }
+
+        let errors = self
+            .configuration_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(source, err)| format!("{}: {}", source, err))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(NotReadyReason::ConfigurationInvalid(errors.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// check_kubernetes_api checks the connectivity with the Kubernetes API
+    /// server, caching the result for KUBERNETES_API_CHECK_TTL
+    async fn check_kubernetes_api(&self) -> Result<(), String> {
+        let Some(client) = &self.kubernetes_client else {
+            return Ok(());
+        };
+
+        let mut cached = self.kubernetes_check.lock().await;
+        if let Some((checked_at, result)) = cached.as_ref() {
+            if checked_at.elapsed() < KUBERNETES_API_CHECK_TTL {
+                return result.clone();
+            }
+        }
+
+        let result =
+            match tokio::time::timeout(KUBERNETES_API_CHECK_TIMEOUT, client.apiserver_version())
+                .await
+            {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("timeout while contacting the API server".to_string()),
+            };
+
+        *cached = Some((Instant::now(), result.clone()));
+        result
+    }
+
+    /// report_health keeps the status of the passed services in the gRPC
+    /// health service in sync with the readiness of the plugin. When the
+    /// Kubernetes API is used, its connectivity is reported as the status
+    /// of KUBERNETES_API_HEALTH_SERVICE
+    pub async fn report_health(
+        self: Arc<Self>,
+        mut reporter: HealthReporter,
+        services: Vec<&'static str>,
+    ) {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let status = match self.compute_readiness().await {
+                Ok(()) => ServingStatus::Serving,
+                Err(_) => ServingStatus::NotServing,
+            };
+            reporter.set_service_status("", status).await;
+            for service in &services {
+                reporter.set_service_status(service, status).await;
+            }
+
+            if self.kubernetes_client.is_some() {
+                let status = match self.check_kubernetes_api().await {
+                    Ok(()) => ServingStatus::Serving,
+                    Err(_) => ServingStatus::NotServing,
+                };
+                reporter
+                    .set_service_status(KUBERNETES_API_HEALTH_SERVICE, status)
+                    .await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_not_ready_until_initialized() {
+        let state = PluginState::new(None);
+        assert_eq!(
+            state.check_readiness().await,
+            Err(NotReadyReason::ServerNotInitialized)
+        );
+
+        state.mark_server_initialized();
+        assert_eq!(
+            state.check_readiness().await,
+            Err(NotReadyReason::ConfigurationNotLoaded)
+        );
+
+        state.mark_configuration_loaded(&[("policy", Err("invalid YAML".to_string()))]);
+        assert_eq!(
+            state.check_readiness().await,
+            Err(NotReadyReason::ConfigurationInvalid(
+                "policy: invalid YAML".to_string()
+            ))
+        );
+
+        state.set_configuration_status("policy", Ok(()));
+        assert_eq!(state.check_readiness().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_ready_without_kubernetes_api() {
+        let config = kube::Config::new("http://127.0.0.1:1".parse().unwrap());
+        let state = PluginState::new(Some(kube::Client::try_from(config).unwrap()));
+        state.mark_server_initialized();
+        state.mark_configuration_loaded(&[]);
+
+        assert!(state.check_kubernetes_api().await.is_err());
+        assert_eq!(state.check_readiness().await, Ok(()));
+    }
+
+    #[test]
+    fn test_reconcile_errors() {
+        let state = PluginState::new(None);
+        state.set_reconcile_errors("default/cluster-example", vec!["conflict".to_string()]);
+        assert_eq!(
+            state.reconcile_errors("default/cluster-example"),
+            vec!["conflict"]
+        );
+        assert!(state.reconcile_errors("default/other").is_empty());
+
+        state.set_reconcile_errors("default/cluster-example", Vec::new());
+        assert!(state.reconcile_errors("default/cluster-example").is_empty());
+    }
+}